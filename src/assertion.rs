@@ -0,0 +1,240 @@
+use crate::error::{Result, TokenGenerationError};
+use crate::json_structs::{
+    check_availability, Claims, GoogleResponse, ServiceAccountInfoJson, ValidResponse,
+};
+use crate::provider::AccessToken;
+use crate::signer::{parse_p12_private_key, parse_private_key, sign_local};
+use crate::universe::{check_endpoint, DEFAULT_UNIVERSE_DOMAIN};
+use crate::usage::Usage;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::EncodingKey;
+
+/// The content type of the token request body, see [`ServiceAccountKey::token_request_body`].
+pub static CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+static GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+
+/// This struct signs the jwt assertions of a service account without sending them.
+///
+/// It is the transport independent part of [`crate::AuthConfig`]: the assertion is sent with any
+/// http client as `POST` request to the [`ServiceAccountKey::token_uri`] and the response is read
+/// with [`parse_token_response`]. Without the `http` feature, this is the only way to obtain tokens.
+pub struct ServiceAccountKey {
+    client_email: String,
+    token_uri: String,
+    universe_domain: String,
+    key: EncodingKey,
+    key_id: Option<String>,
+}
+
+impl ServiceAccountKey {
+    /// This function reads the private key of a service account key file.
+    /// # Errors
+    /// See [`crate::Error`] for a more detailed answer.
+    /// An unusable key results in [`crate::Error::InvalidPrivateKey`].
+    pub fn build(service_account_json_str: &str) -> Result<Self> {
+        let account = ServiceAccountInfoJson::parse(service_account_json_str)?;
+        let universe_domain = account
+            .universe_domain
+            .unwrap_or_else(|| DEFAULT_UNIVERSE_DOMAIN.to_string());
+        check_endpoint(&account.token_uri, &universe_domain)?;
+        Ok(Self {
+            key: EncodingKey::from_rsa_der(&parse_private_key(
+                account.private_key.expose_secret(),
+            )?),
+            key_id: Some(account.private_key_id),
+            client_email: account.client_email,
+            token_uri: account.token_uri,
+            universe_domain,
+        })
+    }
+
+    /// This function reads the private key of a legacy P12 key file, see [`crate::AuthConfig::build_from_p12`].
+    /// # Errors
+    /// An unreadable file or key results in [`crate::Error::InvalidPrivateKey`].
+    pub fn build_from_p12(p12: &[u8], client_email: &str, token_uri: &str) -> Result<Self> {
+        check_endpoint(token_uri, DEFAULT_UNIVERSE_DOMAIN)?;
+        Ok(Self {
+            key: EncodingKey::from_rsa_der(&parse_p12_private_key(p12)?),
+            key_id: None,
+            client_email: client_email.to_string(),
+            token_uri: token_uri.to_string(),
+            universe_domain: DEFAULT_UNIVERSE_DOMAIN.to_string(),
+        })
+    }
+
+    /// The email of the service account.
+    #[must_use]
+    pub fn client_email(&self) -> &str {
+        &self.client_email
+    }
+
+    /// The url the token request is sent to.
+    #[must_use]
+    pub fn token_uri(&self) -> &str {
+        &self.token_uri
+    }
+
+    /// The universe domain the tokens are valid in, `googleapis.com` for the public Google cloud.
+    #[must_use]
+    pub fn universe_domain(&self) -> &str {
+        &self.universe_domain
+    }
+
+    /// The id of the key, sent as `kid` header. P12 keys carry no key id.
+    #[must_use]
+    pub fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    /// This function signs a jwt assertion for the `usage`.
+    /// # Params
+    /// **`usage`: String**<br>
+    /// Each google api request requires individual permissions to be executed.
+    /// See here for more information: [Google Scopes](https://developers.google.com/identity/protocols/oauth2/scopes?hl=en).
+    ///
+    /// **`issued_at`: `DateTime`**<br>
+    /// The `iat` claim, usually a few seconds before now, so a clock running fast does not matter.
+    ///
+    /// **`lifetime`: i64**<br>
+    /// The lifetime in seconds, between 30 and 3600.
+    /// # Errors
+    /// See [`crate::Error`] for a more detailed answer.
+    pub fn sign_assertion(
+        &self,
+        usage: &Usage,
+        issued_at: DateTime<Utc>,
+        lifetime: i64,
+    ) -> Result<String> {
+        if !(30..=3600).contains(&lifetime) {
+            return Err(TokenGenerationError::InvalidLifetime(lifetime));
+        }
+        let claims = Claims::new(
+            self.client_email.clone(),
+            usage.as_string(),
+            self.token_uri.clone(),
+            issued_at,
+            lifetime,
+        );
+        sign_local(&self.key, self.key_id.as_ref(), &claims)
+    }
+
+    /// This function signs a jwt assertion and returns the body of the token request, see [`CONTENT_TYPE`].
+    /// # Errors
+    /// See [`ServiceAccountKey::sign_assertion`].
+    pub fn token_request_body(
+        &self,
+        usage: &Usage,
+        issued_at: DateTime<Utc>,
+        lifetime: i64,
+    ) -> Result<String> {
+        Ok(token_request_body(
+            &self.sign_assertion(usage, issued_at, lifetime)?,
+        ))
+    }
+}
+
+/// The `Debug` output never contains the private key.
+impl std::fmt::Debug for ServiceAccountKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServiceAccountKey")
+            .field("client_email", &self.client_email)
+            .field("universe_domain", &self.universe_domain)
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The form encoded body of a token request with a signed assertion.
+pub(crate) fn token_request_body(assertion: &str) -> String {
+    format!("grant_type={GRANT_TYPE}&assertion={assertion}")
+}
+
+/// This function reads the response of a token request.
+/// # Params
+/// **`status`: u16**<br>
+/// The HTTP status of the response.
+///
+/// **`body`: String**<br>
+/// The body of the response.
+///
+/// **`received_at`: `DateTime`**<br>
+/// The point in time the response arrived, the token expires `expires_in` seconds later.
+/// # Errors
+/// `429` and `5xx` responses result in [`crate::Error::ServiceUnavailable`],
+/// error responses of the authentication service in [`crate::Error::AuthenticationError`].
+/// See [`crate::Error::kind`] to decide whether to retry.
+pub fn parse_token_response(
+    status: u16,
+    body: &str,
+    received_at: DateTime<Utc>,
+) -> Result<AccessToken> {
+    check_availability(status, None, body)?;
+    let response = GoogleResponse::<ValidResponse>::parse(status, body)?;
+    Ok(AccessToken::expiring_in(
+        response.access_token,
+        response.expires_in,
+        received_at,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::error::ErrorKind;
+    use std::fs;
+
+    #[test]
+    fn test_token_request() {
+        let key = ServiceAccountKey::build(
+            &fs::read_to_string("tests/invalid-value-client.json").unwrap(),
+        )
+        .unwrap();
+        let body = key
+            .token_request_body(&Usage::CloudVision, Utc::now(), 3600)
+            .unwrap();
+        let assertion = body
+            .strip_prefix("grant_type=urn:ietf:params:oauth:grant-type:jwt-bearer&assertion=")
+            .unwrap();
+        let header = jsonwebtoken::decode_header(assertion).unwrap();
+        assert_eq!(header.kid.as_deref(), key.key_id());
+        assert!(matches!(
+            key.sign_assertion(&Usage::CloudVision, Utc::now(), 3601),
+            Err(TokenGenerationError::InvalidLifetime(3601))
+        ));
+        assert!(!format!("{key:?}").contains("PRIVATE KEY"));
+
+        let mut account: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string("tests/invalid-value-client.json").unwrap())
+                .unwrap();
+        account["token_uri"] = "https://accounts.google.com/o/oauth2/token".into();
+        assert!(ServiceAccountKey::build(&account.to_string()).is_ok());
+    }
+
+    #[test]
+    fn test_parse_token_response() {
+        let received_at = Utc::now();
+        let token = parse_token_response(
+            200,
+            r#"{"access_token": "token", "expires_in": 3599, "token_type": "Bearer"}"#,
+            received_at,
+        )
+        .unwrap();
+        assert_eq!(token.as_str(), "token");
+        assert_eq!(
+            token.expires_at(),
+            received_at + chrono::Duration::seconds(3599)
+        );
+
+        let error = parse_token_response(
+            400,
+            r#"{"error": "invalid_scope", "error_description": "Invalid scope."}"#,
+            received_at,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidScope);
+        assert!(parse_token_response(503, "", received_at)
+            .unwrap_err()
+            .is_retryable());
+    }
+}
@@ -0,0 +1,80 @@
+//! The arguments shared by the binaries: the key file and the scopes.
+use clap::Args;
+use google_jwt_auth::usage::Usage;
+use std::io::Read;
+use std::path::PathBuf;
+
+/// Scopes without a scheme are short names of the google scopes, e.g. `cloud-platform`.
+static SCOPE_PREFIX: &str = "https://www.googleapis.com/auth/";
+/// The environment variable with the path of the key file, used if no key is given.
+static CREDENTIALS_ENV: &str = "GOOGLE_APPLICATION_CREDENTIALS";
+
+/// Where the service account key file is read from.
+/// Without any of these options, the file at `GOOGLE_APPLICATION_CREDENTIALS` is read.
+#[derive(Args, Debug)]
+pub struct KeySource {
+    /// The path of the service account key file, `-` reads it from stdin.
+    #[arg(long, global = true, conflicts_with = "key_env")]
+    pub key_file: Option<PathBuf>,
+    /// The name of an environment variable that contains the service account key file.
+    #[arg(long, global = true)]
+    pub key_env: Option<String>,
+}
+
+/// The scopes of a token.
+#[derive(Args, Debug)]
+pub struct Scopes {
+    /// The scopes of the token, full urls or short names like `devstorage.read_only`.
+    #[arg(
+        long = "scope",
+        value_delimiter = ',',
+        default_value = "cloud-platform"
+    )]
+    pub scopes: Vec<String>,
+}
+
+impl Scopes {
+    /// The scopes as full urls.
+    pub fn urls(&self) -> Vec<String> {
+        self.scopes
+            .iter()
+            .map(|scope| {
+                if scope.contains("://") {
+                    scope.clone()
+                } else {
+                    format!("{SCOPE_PREFIX}{scope}")
+                }
+            })
+            .collect()
+    }
+
+    /// The scopes as one usage, google accepts a space separated list of scopes.
+    pub fn usage(&self) -> Usage {
+        Usage::Custom(self.urls().join(" "))
+    }
+}
+
+/// Reads the key file from the path, stdin or the environment variable of the `source`.
+pub fn read_key(source: &KeySource) -> Result<String, String> {
+    if let Some(name) = &source.key_env {
+        return std::env::var(name)
+            .map_err(|_| format!("The environment variable '{name}' is not set."));
+    }
+    let path = match &source.key_file {
+        Some(path) => path.clone(),
+        None => std::env::var_os(CREDENTIALS_ENV)
+            .map(PathBuf::from)
+            .ok_or_else(|| {
+                format!("No key given, use --key-file, --key-env or set {CREDENTIALS_ENV}.")
+            })?,
+    };
+    if path.as_os_str() == "-" {
+        let mut key = String::new();
+        std::io::stdin()
+            .read_to_string(&mut key)
+            .map_err(|error| format!("The key cannot be read from stdin: {error}"))?;
+        return Ok(key);
+    }
+    std::fs::read_to_string(&path)
+        .map_err(|error| format!("The key file '{}' cannot be read: {error}", path.display()))
+}
@@ -0,0 +1,423 @@
+//! The `google-jwt-auth-metadata-server` emulates the token endpoints of the GCE metadata server
+//! with a service account key, so unmodified Google client libraries work outside of Google cloud,
+//! e.g. as sidecar container or in integration tests. Point them to it with `GCE_METADATA_HOST`.
+//!
+//! Besides the `token`, `identity`, `email` and `scopes` endpoints of the service account,
+//! it serves the account directory (`?recursive=true` returns it as JSON), `project/project-id`
+//! and `universe/universe-domain`. Instance attributes, the numeric project id
+//! and `wait_for_change` requests are not emulated and answered with `404`.
+#![forbid(unsafe_code)]
+#![warn(
+    clippy::pedantic,
+    clippy::nursery,
+    clippy::unwrap_used,
+    clippy::dbg_macro,
+    clippy::todo
+)]
+
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use clap::Parser;
+use common::{read_key, KeySource, Scopes};
+use google_jwt_auth::provider::TokenProvider;
+use google_jwt_auth::usage::Usage;
+use google_jwt_auth::AuthConfig;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+#[path = "../common/mod.rs"]
+mod common;
+
+static METADATA_FLAVOR: HeaderName = HeaderName::from_static("metadata-flavor");
+static SERVICE_ACCOUNT_PATH: &str = "/computeMetadata/v1/instance/service-accounts/{account}";
+static ACCOUNT_ENTRIES: &str = "aliases\nemail\nidentity\nscopes\ntoken\n";
+
+/// Serves the GCE metadata token endpoints for a Google service account key.
+#[derive(Parser, Debug)]
+#[command(name = "google-jwt-auth-metadata-server", version)]
+struct Cli {
+    #[command(flatten)]
+    key: KeySource,
+    #[command(flatten)]
+    scopes: Scopes,
+    /// The address the server listens on.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+}
+
+/// The credential behind the endpoints, its tokens are buffered and refreshed shortly before they expire.
+struct Account {
+    config: AuthConfig,
+    scopes: Vec<String>,
+    project_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    scopes: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IdentityQuery {
+    audience: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DirectoryQuery {
+    recursive: Option<bool>,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let key = match read_key(&cli.key) {
+        Ok(key) => key,
+        Err(message) => {
+            eprintln!("error: {message}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let config = match AuthConfig::build(&key, &cli.scopes.usage()) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("error: {error}");
+            eprintln!("hint: {}", error.remediation());
+            return ExitCode::FAILURE;
+        }
+    };
+    let account = Arc::new(Account {
+        config,
+        scopes: cli.scopes.urls(),
+        project_id: project_id(&key),
+    });
+    let listener = match tokio::net::TcpListener::bind(cli.listen).await {
+        Ok(listener) => listener,
+        Err(error) => {
+            eprintln!("error: {} cannot be bound: {error}", cli.listen);
+            return ExitCode::FAILURE;
+        }
+    };
+    eprintln!(
+        "serving {} on {}",
+        account.config.client_email(),
+        cli.listen
+    );
+    if let Err(error) = axum::serve(listener, router(account)).await {
+        eprintln!("error: {error}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+fn router(account: Arc<Account>) -> Router {
+    Router::new()
+        .route("/", get(|| async { StatusCode::OK }))
+        .route("/computeMetadata/v1/project/project-id", get(project))
+        .route(
+            "/computeMetadata/v1/universe/universe-domain",
+            get(universe),
+        )
+        .route(&format!("{SERVICE_ACCOUNT_PATH}/"), get(directory))
+        .route(&format!("{SERVICE_ACCOUNT_PATH}/token"), get(token))
+        .route(&format!("{SERVICE_ACCOUNT_PATH}/identity"), get(identity))
+        .route(&format!("{SERVICE_ACCOUNT_PATH}/email"), get(email))
+        .route(&format!("{SERVICE_ACCOUNT_PATH}/scopes"), get(scopes))
+        .layer(middleware::from_fn(metadata_flavor))
+        .with_state(account)
+}
+
+/// Rejects requests without `Metadata-Flavor: Google` like the metadata server,
+/// the header protects against server side request forgery. Every response carries it as well.
+async fn metadata_flavor(request: Request, next: Next) -> Response {
+    let google = HeaderValue::from_static("Google");
+    let mut response = if request.headers().get(&METADATA_FLAVOR) == Some(&google) {
+        next.run(request).await
+    } else {
+        (
+            StatusCode::FORBIDDEN,
+            "Missing required header \"Metadata-Flavor\": \"Google\"\n",
+        )
+            .into_response()
+    };
+    response
+        .headers_mut()
+        .insert(METADATA_FLAVOR.clone(), google);
+    response
+}
+
+async fn token(
+    State(account): State<Arc<Account>>,
+    Path(name): Path<String>,
+    Query(query): Query<TokenQuery>,
+) -> Response {
+    if !account.serves(&name) {
+        return not_found();
+    }
+    let token = match query.scopes {
+        Some(scopes) => {
+            account
+                .config
+                .access_token_for(&Usage::Custom(scopes.replace(',', " ")))
+                .await
+        }
+        None => account.config.access_token().await,
+    };
+    match token {
+        Ok(token) => Json(serde_json::json!({
+            "access_token": token.as_str(),
+            "expires_in": (token.expires_at() - chrono::Utc::now()).num_seconds(),
+            "token_type": "Bearer",
+        }))
+        .into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{error}\n")).into_response(),
+    }
+}
+
+async fn identity(
+    State(account): State<Arc<Account>>,
+    Path(name): Path<String>,
+    Query(query): Query<IdentityQuery>,
+) -> Response {
+    if !account.serves(&name) {
+        return not_found();
+    }
+    let Some(audience) = query.audience.filter(|audience| !audience.is_empty()) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "non-empty audience parameter required\n",
+        )
+            .into_response();
+    };
+    match account.config.generate_id_token(&audience).await {
+        Ok(token) => token.expose_secret().to_string().into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{error}\n")).into_response(),
+    }
+}
+
+/// Lists the entries of the service account, `?recursive=true` returns them as JSON like the metadata server.
+async fn directory(
+    State(account): State<Arc<Account>>,
+    Path(name): Path<String>,
+    Query(query): Query<DirectoryQuery>,
+) -> Response {
+    if !account.serves(&name) {
+        return not_found();
+    }
+    if query.recursive != Some(true) {
+        return ACCOUNT_ENTRIES.into_response();
+    }
+    Json(serde_json::json!({
+        "aliases": ["default"],
+        "email": account.config.client_email(),
+        "scopes": account.scopes,
+    }))
+    .into_response()
+}
+
+async fn project(State(account): State<Arc<Account>>) -> Response {
+    account
+        .project_id
+        .clone()
+        .map_or_else(not_found, IntoResponse::into_response)
+}
+
+async fn universe(State(account): State<Arc<Account>>) -> Response {
+    account.config.universe_domain().to_string().into_response()
+}
+
+async fn email(State(account): State<Arc<Account>>, Path(name): Path<String>) -> Response {
+    if !account.serves(&name) {
+        return not_found();
+    }
+    account.config.client_email().to_string().into_response()
+}
+
+async fn scopes(State(account): State<Arc<Account>>, Path(name): Path<String>) -> Response {
+    if !account.serves(&name) {
+        return not_found();
+    }
+    let mut scopes = account.scopes.join("\n");
+    scopes.push('\n');
+    scopes.into_response()
+}
+
+impl Account {
+    /// Whether the path names this service account, by its email or as `default`.
+    fn serves(&self, name: &str) -> bool {
+        name == "default" || name == self.config.client_email()
+    }
+}
+
+/// The `project_id` of the key file, it is optional for keys created outside of the console.
+fn project_id(key: &str) -> Option<String> {
+    serde_json::from_str::<serde_json::Value>(key)
+        .ok()?
+        .get("project_id")?
+        .as_str()
+        .map(str::to_string)
+}
+
+fn not_found() -> Response {
+    (StatusCode::NOT_FOUND, "Not Found\n").into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use axum::routing::post;
+
+    async fn serve(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        url
+    }
+
+    /// Starts the metadata server for the fake key and a token endpoint
+    /// that answers access and id token requests alike. Returns its url and the key.
+    async fn start() -> (String, serde_json::Value) {
+        let google = serve(Router::new().route(
+            "/token",
+            post(|| async {
+                Json(serde_json::json!({
+                    "access_token": "access-token",
+                    "expires_in": 3600,
+                    "id_token": "id-token",
+                }))
+            }),
+        ))
+        .await;
+        let mut key: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string("tests/invalid-value-client.json").unwrap(),
+        )
+        .unwrap();
+        key["token_uri"] = format!("{google}/token").into();
+        let scopes = Scopes {
+            scopes: vec![String::from("cloud-platform")],
+        };
+        let config = AuthConfig::build(&key.to_string(), &scopes.usage()).unwrap();
+        let url = serve(router(Arc::new(Account {
+            config,
+            scopes: scopes.urls(),
+            project_id: project_id(&key.to_string()),
+        })))
+        .await;
+        (url, key)
+    }
+
+    #[tokio::test]
+    async fn test_metadata_server() {
+        let (url, key) = start().await;
+        let client = reqwest::Client::new();
+        let get = |path: &str| {
+            client
+                .get(format!(
+                    "{url}/computeMetadata/v1/instance/service-accounts/{path}"
+                ))
+                .header("Metadata-Flavor", "Google")
+        };
+
+        let response = client
+            .get(format!(
+                "{url}/computeMetadata/v1/instance/service-accounts/default/email"
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(response.headers()["metadata-flavor"], "Google");
+
+        let email = get("default/email")
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(email, key["client_email"]);
+        let token: serde_json::Value = get(&format!("{email}/token"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(token["access_token"], "access-token");
+        assert_eq!(token["token_type"], "Bearer");
+        let scopes = get("default/scopes")
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(scopes, "https://www.googleapis.com/auth/cloud-platform\n");
+
+        let response = get("default/identity").send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = get("default/identity?audience=https://service.example.com")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "id-token");
+        let response = get("other@example.com/email").send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_account_info() {
+        let (url, key) = start().await;
+        let client = reqwest::Client::new();
+        let get = |path: &str| {
+            client
+                .get(format!("{url}/computeMetadata/v1/{path}"))
+                .header("Metadata-Flavor", "Google")
+        };
+
+        let info: serde_json::Value = get("instance/service-accounts/default/?recursive=true")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(info["email"], key["client_email"]);
+        assert_eq!(info["aliases"], serde_json::json!(["default"]));
+        assert_eq!(
+            info["scopes"],
+            serde_json::json!(["https://www.googleapis.com/auth/cloud-platform"])
+        );
+        let entries = get("instance/service-accounts/default/")
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(entries, ACCOUNT_ENTRIES);
+
+        let project = get("project/project-id")
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(project, key["project_id"]);
+        let universe = get("universe/universe-domain")
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(universe, "googleapis.com");
+    }
+}
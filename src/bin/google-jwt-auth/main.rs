@@ -0,0 +1,183 @@
+//! The `google-jwt-auth` command line tool prints the tokens of a service account key,
+//! e.g. as replacement for `gcloud auth print-access-token` in minimal containers.
+#![forbid(unsafe_code)]
+#![warn(
+    clippy::pedantic,
+    clippy::nursery,
+    clippy::unwrap_used,
+    clippy::dbg_macro,
+    clippy::todo
+)]
+
+use clap::{Parser, Subcommand};
+use common::{read_key, KeySource, Scopes};
+use google_jwt_auth::provider::TokenProvider;
+use google_jwt_auth::usage::Usage;
+use google_jwt_auth::{AuthConfig, Error};
+use std::process::ExitCode;
+
+#[path = "../common/mod.rs"]
+mod common;
+mod doctor;
+
+/// Prints access and id tokens of a Google service account key.
+#[derive(Parser, Debug)]
+#[command(name = "google-jwt-auth", version)]
+struct Cli {
+    #[command(flatten)]
+    key: KeySource,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Prints an access token.
+    AccessToken {
+        #[command(flatten)]
+        scopes: Scopes,
+    },
+    /// Prints an `OpenID Connect` id token.
+    IdToken {
+        /// The audience of the token, usually the url of the receiving service.
+        #[arg(long)]
+        audience: String,
+    },
+    /// Prints the expiry and the scopes of an access token as json, without the token itself.
+    TokenInfo {
+        #[command(flatten)]
+        scopes: Scopes,
+    },
+    /// Checks the key file offline and explains why it does not work.
+    Doctor {
+        #[command(flatten)]
+        scopes: Scopes,
+        /// Requests a token as well, to find problems only Google can tell.
+        #[arg(long)]
+        exchange: bool,
+    },
+}
+
+/// A failure of the command, printed to stderr.
+/// A report of the doctor command with failed checks is printed to stdout.
+enum Failure {
+    Input(String),
+    Token(Error),
+    Report(String),
+}
+
+impl From<Error> for Failure {
+    fn from(error: Error) -> Self {
+        Self::Token(error)
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(output) => {
+            println!("{output}");
+            ExitCode::SUCCESS
+        }
+        Err(Failure::Input(message)) => {
+            eprintln!("error: {message}");
+            ExitCode::FAILURE
+        }
+        Err(Failure::Token(error)) => {
+            eprintln!("error: {error}");
+            eprintln!("hint: {}", error.remediation());
+            ExitCode::FAILURE
+        }
+        Err(Failure::Report(report)) => {
+            println!("{report}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<String, Failure> {
+    let key = read_key(&cli.key).map_err(Failure::Input)?;
+    match cli.command {
+        Command::AccessToken { scopes } => {
+            let config = AuthConfig::build(&key, &scopes.usage())?;
+            Ok(config.access_token().await?.as_str().to_string())
+        }
+        Command::IdToken { audience } => {
+            let config = AuthConfig::build(&key, &Usage::CloudPlatform)?;
+            Ok(config
+                .generate_id_token(&audience)
+                .await?
+                .expose_secret()
+                .to_string())
+        }
+        Command::TokenInfo { scopes } => {
+            let config = AuthConfig::build(&key, &scopes.usage())?;
+            let token = config.access_token().await?;
+            let info = serde_json::json!({
+                "token_type": "Bearer",
+                "expires_at": token.expires_at().to_rfc3339(),
+                "expires_in": (token.expires_at() - chrono::Utc::now()).num_seconds(),
+                "scopes": scopes.urls(),
+                "key_id": config.active_key_id(),
+                "universe_domain": config.universe_domain(),
+                "quota_project_id": config.quota_project_id(),
+            });
+            serde_json::to_string_pretty(&info).map_err(|error| Failure::Input(error.to_string()))
+        }
+        Command::Doctor { scopes, exchange } => {
+            let mut checks = doctor::check_offline(&key, &scopes.urls(), chrono::Utc::now());
+            if exchange {
+                checks.push(doctor::check_exchange(&key, &scopes.urls()).await);
+            }
+            let report = checks
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n");
+            if checks.iter().any(doctor::Check::failed) {
+                return Err(Failure::Report(report));
+            }
+            Ok(report)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_arguments() {
+        Cli::command().debug_assert();
+        let cli = Cli::try_parse_from([
+            "google-jwt-auth",
+            "access-token",
+            "--scope",
+            "devstorage.read_only,https://www.googleapis.com/auth/pubsub",
+            "--key-file",
+            "-",
+        ])
+        .unwrap();
+        let Command::AccessToken { scopes } = cli.command else {
+            panic!("unexpected command {:?}", cli.command);
+        };
+        assert_eq!(
+            scopes.usage().to_string(),
+            "https://www.googleapis.com/auth/devstorage.read_only https://www.googleapis.com/auth/pubsub"
+        );
+        assert!(Cli::try_parse_from([
+            "google-jwt-auth",
+            "id-token",
+            "--audience",
+            "https://service.example.com",
+            "--key-file",
+            "key.json",
+            "--key-env",
+            "KEY"
+        ])
+        .is_err());
+    }
+}
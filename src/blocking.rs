@@ -0,0 +1,382 @@
+use crate::assertion::{token_request_body, CONTENT_TYPE};
+use crate::cache::BlockingTokenCache;
+use crate::clock::Clock;
+use crate::error::Result;
+use crate::json_structs::{Claims, GoogleResponse, IdTokenResponse, ValidResponse};
+use crate::provider::AccessToken;
+use crate::retry::RetryPolicy;
+use crate::secret::SecretString;
+use crate::signer::AssertionSigner;
+use crate::usage::Usage;
+use crate::{
+    check_lifetime, map_timeout, normalize_scope, parse_date_header, scoped_entry,
+    AssertionResponse, Error, DEFAULT_LIFETIME,
+};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// This struct works like [`crate::AuthConfig`] without an async runtime.
+///
+/// The token requests block the calling thread, the tokens of [`AuthConfig::access_token`]
+/// are buffered and refreshed shortly before they expire.
+/// Only local keys are supported, remote and custom signers require the async api.
+/// This structure is intended to be reused by the client for several token generation requests.
+pub struct AuthConfig {
+    inner: crate::AuthConfig,
+    cache: Arc<BlockingTokenCache>,
+    scoped_caches: Mutex<HashMap<String, Arc<BlockingTokenCache>>>,
+    client: OnceLock<reqwest::blocking::Client>,
+}
+
+impl AuthConfig {
+    /// This function generates an auth configuration with the provided information,
+    /// see [`crate::AuthConfig::build`].
+    /// # Errors
+    /// See [`Error`] for a more detailed answer.
+    pub fn build(service_account_json_str: &str, usage: &Usage) -> Result<Self> {
+        Ok(crate::AuthConfig::build(service_account_json_str, usage)?.into())
+    }
+
+    /// This function generates an auth configuration that holds several keys of one service account,
+    /// see [`crate::AuthConfig::build_with_keys`].
+    /// # Errors
+    /// See [`Error`] for a more detailed answer.
+    pub fn build_with_keys<S: AsRef<str>>(
+        service_account_json_strs: &[S],
+        usage: &Usage,
+    ) -> Result<Self> {
+        Ok(crate::AuthConfig::build_with_keys(service_account_json_strs, usage)?.into())
+    }
+
+    /// This function generates an auth configuration from a legacy P12 key file,
+    /// see [`crate::AuthConfig::build_from_p12`].
+    /// # Errors
+    /// See [`Error`] for a more detailed answer.
+    pub fn build_from_p12(
+        p12: &[u8],
+        client_email: &str,
+        token_uri: &str,
+        usage: &Usage,
+    ) -> Result<Self> {
+        Ok(crate::AuthConfig::build_from_p12(p12, client_email, token_uri, usage)?.into())
+    }
+
+    /// See [`crate::AuthConfig::with_retry_policy`].
+    #[must_use]
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.inner = self.inner.with_retry_policy(retry_policy);
+        self
+    }
+
+    /// See [`crate::AuthConfig::with_connect_timeout`].
+    #[must_use]
+    pub fn with_connect_timeout(mut self, connect_timeout: Option<Duration>) -> Self {
+        self.inner = self.inner.with_connect_timeout(connect_timeout);
+        self.client = OnceLock::new();
+        self
+    }
+
+    /// See [`crate::AuthConfig::with_request_timeout`].
+    #[must_use]
+    pub fn with_request_timeout(mut self, request_timeout: Option<Duration>) -> Self {
+        self.inner = self.inner.with_request_timeout(request_timeout);
+        self
+    }
+
+    /// See [`crate::AuthConfig::with_deadline`].
+    #[must_use]
+    pub fn with_deadline(mut self, deadline: Option<Duration>) -> Self {
+        self.inner = self.inner.with_deadline(deadline);
+        self
+    }
+
+    /// See [`crate::AuthConfig::with_clock`].
+    #[must_use]
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.inner = self.inner.with_clock(clock);
+        self
+    }
+
+    /// See [`crate::AuthConfig::with_iat_backdate`].
+    #[must_use]
+    pub fn with_iat_backdate(mut self, iat_backdate: Duration) -> Self {
+        self.inner = self.inner.with_iat_backdate(iat_backdate);
+        self
+    }
+
+    /// See [`crate::AuthConfig::with_quota_project_id`].
+    #[must_use]
+    pub fn with_quota_project_id(mut self, quota_project_id: Option<&str>) -> Self {
+        self.inner = self.inner.with_quota_project_id(quota_project_id);
+        self
+    }
+
+    /// The universe domain the tokens are valid in, `googleapis.com` for the public Google cloud.
+    #[must_use]
+    pub fn universe_domain(&self) -> &str {
+        self.inner.universe_domain()
+    }
+
+    /// The project that is billed for the api requests, see [`crate::AuthConfig::quota_project_id`].
+    #[must_use]
+    pub fn quota_project_id(&self) -> Option<&str> {
+        self.inner.quota_project_id()
+    }
+
+    /// The id of the key that signs the next assertion, if it is known in advance.
+    #[must_use]
+    pub fn active_key_id(&self) -> Option<String> {
+        self.inner.active_key_id()
+    }
+
+    /// See [`crate::AuthConfig::clock_skew`].
+    #[must_use]
+    pub fn clock_skew(&self) -> i64 {
+        self.inner.clock_skew()
+    }
+
+    /// An authentication token (short: `auth_token`) will be requested from Google,
+    /// see [`crate::AuthConfig::generate_auth_token`].
+    /// # Errors
+    /// See [`Error`] for a more detailed answer.
+    /// # Returns
+    /// The above-mentioned `auth_token` as String.
+    pub fn generate_auth_token(&self, lifetime: i64) -> Result<SecretString> {
+        Ok(self
+            .request_access_token(&self.inner.scope, lifetime, self.inner.deadline)?
+            .into_secret())
+    }
+
+    /// Works like [`AuthConfig::generate_auth_token`], but fails with [`Error::Timeout`]
+    /// if no token is available within the `deadline` instead of the configured one.
+    /// # Errors
+    /// See [`Error`] for a more detailed answer.
+    pub fn generate_auth_token_with_deadline(
+        &self,
+        lifetime: i64,
+        deadline: Duration,
+    ) -> Result<SecretString> {
+        Ok(self
+            .request_access_token(&self.inner.scope, lifetime, Some(deadline))?
+            .into_secret())
+    }
+
+    /// A token that is valid for at least five more minutes.
+    /// The token is buffered and refreshed shortly before it expires.
+    /// # Errors
+    /// See [`Error`] for a more detailed answer.
+    pub fn access_token(&self) -> Result<AccessToken> {
+        self.cache.get_or_refresh(self.inner.clock.now(), || {
+            self.request_access_token(&self.inner.scope, DEFAULT_LIFETIME, self.inner.deadline)
+        })
+    }
+
+    /// Works like [`AuthConfig::access_token`], but with the scope of `usage` instead of the configured one,
+    /// see [`crate::AuthConfig::access_token_for`].
+    /// # Errors
+    /// See [`Error`] for a more detailed answer.
+    pub fn access_token_for(&self, usage: &Usage) -> Result<AccessToken> {
+        let scope = normalize_scope(&usage.as_string());
+        self.scoped_cache(&scope)
+            .get_or_refresh(self.inner.clock.now(), || {
+                self.request_access_token(&scope, DEFAULT_LIFETIME, self.inner.deadline)
+            })
+    }
+
+    /// An `OpenID Connect` id token will be requested from Google,
+    /// see [`crate::AuthConfig::generate_id_token`].
+    /// # Errors
+    /// See [`Error`] for a more detailed answer.
+    /// # Returns
+    /// The above-mentioned id token as String.
+    pub fn generate_id_token(&self, audience: &str) -> Result<SecretString> {
+        self.request_token::<IdTokenResponse>(
+            &|| self.inner.id_token_claims(audience),
+            self.inner.deadline,
+        )
+    }
+
+    fn request_access_token(
+        &self,
+        scope: &str,
+        lifetime: i64,
+        deadline: Option<Duration>,
+    ) -> Result<AccessToken> {
+        check_lifetime(lifetime)?;
+        self.request_token::<ValidResponse>(&|| self.inner.claims(scope, lifetime), deadline)
+    }
+
+    /// Requests a token with the active key and falls through to the next keys on `invalid_grant`,
+    /// like the async api does.
+    fn request_token<R: AssertionResponse>(
+        &self,
+        claims: &dyn Fn() -> Claims,
+        deadline: Option<Duration>,
+    ) -> Result<R::Token> {
+        let deadline = deadline.map(|deadline| (Instant::now() + deadline, deadline));
+        let mut rotation = self.inner.key_rotation();
+        while let Some(signer) = rotation.next_signer() {
+            let result = self
+                .inner
+                .retry_policy
+                .run_blocking(|| self.request_signed_token::<R>(signer, claims, deadline));
+            if let Some(result) = rotation.complete(result) {
+                return result;
+            }
+        }
+        rotation.finish()
+    }
+
+    /// Sends an assertion and resends it once if it got rejected for its timeframe
+    /// and the `Date` header of the response reveals a different clock skew.
+    fn request_signed_token<R: AssertionResponse>(
+        &self,
+        signer: &AssertionSigner,
+        claims: &dyn Fn() -> Claims,
+        deadline: Option<(Instant, Duration)>,
+    ) -> Result<R::Token> {
+        let mut server_date = None;
+        let result = self.send_assertion::<R>(signer, &claims(), deadline, &mut server_date);
+        if self.inner.resends_assertion(&result, server_date) {
+            return self.send_assertion::<R>(signer, &claims(), deadline, &mut None);
+        }
+        result
+    }
+
+    fn send_assertion<R: AssertionResponse>(
+        &self,
+        signer: &AssertionSigner,
+        claims: &Claims,
+        deadline: Option<(Instant, Duration)>,
+        server_date: &mut Option<DateTime<Utc>>,
+    ) -> Result<R::Token> {
+        let mut request_timeout = self.inner.http.request_timeout;
+        if let Some((deadline_at, deadline)) = deadline {
+            let remaining = deadline_at.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::Timeout(format!(
+                    "No token within the deadline of {deadline:?}."
+                )));
+            }
+            request_timeout =
+                Some(request_timeout.map_or(remaining, |timeout| timeout.min(remaining)));
+        }
+        let assertion = signer.sign_blocking(claims)?;
+        let resp = self.post_assertion(&assertion, request_timeout)?;
+        *server_date = parse_date_header(resp.headers());
+        let resp = GoogleResponse::<R>::read_blocking(resp).map_err(map_timeout)?;
+        Ok(resp.into_token(&self.inner))
+    }
+
+    fn post_assertion(
+        &self,
+        assertion: &str,
+        request_timeout: Option<Duration>,
+    ) -> Result<reqwest::blocking::Response> {
+        let mut request = self
+            .http_client()?
+            .post(&self.inner.aud)
+            .header(reqwest::header::CONTENT_TYPE, CONTENT_TYPE)
+            .body(token_request_body(assertion));
+        if let Some(request_timeout) = request_timeout {
+            request = request.timeout(request_timeout);
+        }
+        request.send().map_err(map_timeout)
+    }
+
+    /// The client is built on the first request and shared by all later ones.
+    /// The request timeout is set per request, so it can be shortened to the remaining deadline.
+    fn http_client(&self) -> Result<&reqwest::blocking::Client> {
+        if let Some(client) = self.client.get() {
+            return Ok(client);
+        }
+        let client = reqwest::blocking::Client::builder()
+            .connect_timeout(self.inner.http.connect_timeout)
+            .timeout(None)
+            .build()?;
+        Ok(self.client.get_or_init(|| client))
+    }
+
+    /// The token buffer of a normalized scope, the configured scope shares the buffer of [`AuthConfig::access_token`].
+    fn scoped_cache(&self, scope: &str) -> Arc<BlockingTokenCache> {
+        if scope == normalize_scope(&self.inner.scope) {
+            return Arc::clone(&self.cache);
+        }
+        scoped_entry(&self.scoped_caches, scope, BlockingTokenCache::new)
+    }
+}
+
+/// Configurations with an IAM or custom signer fail with [`Error::SignerError`] on every token request.
+impl From<crate::AuthConfig> for AuthConfig {
+    fn from(inner: crate::AuthConfig) -> Self {
+        Self {
+            inner,
+            cache: Arc::new(BlockingTokenCache::new()),
+            scoped_caches: Mutex::default(),
+            client: OnceLock::new(),
+        }
+    }
+}
+
+/// The `Debug` output never contains the private keys.
+impl std::fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::test_server::{TestResponse, TestServer};
+    use crate::tests::{assertion_claims, get_fake_key_json};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_blocking() {
+        let server = TestServer::start(vec![
+            TestResponse::json(503, "{}"),
+            TestResponse::token("first-token"),
+            TestResponse::token("second-token"),
+            TestResponse::token("scoped-token"),
+            TestResponse::json(200, r#"{"id_token": "id-token"}"#),
+        ])
+        .await;
+        let config = AuthConfig::build(&get_fake_key_json("key", &server.url), &Usage::CloudVision)
+            .unwrap()
+            .with_retry_policy(RetryPolicy::default().with_base_delay(Duration::from_millis(1)));
+
+        // The blocking client must not be dropped within the async runtime.
+        tokio::task::spawn_blocking(move || {
+            assert_eq!(config.access_token().unwrap().as_str(), "first-token");
+            assert_eq!(config.access_token().unwrap().as_str(), "first-token");
+            let token = config.generate_auth_token(3600).unwrap();
+            assert_eq!(token.expose_secret(), "second-token");
+            assert!(matches!(
+                config.generate_auth_token(3601),
+                Err(Error::InvalidLifetime(3601))
+            ));
+            let scoped = config.access_token_for(&Usage::CloudPlatform).unwrap();
+            assert_eq!(scoped.as_str(), "scoped-token");
+            let scoped = config.access_token_for(&Usage::CloudPlatform).unwrap();
+            assert_eq!(scoped.as_str(), "scoped-token");
+            let id_token = config.generate_id_token("https://example.com").unwrap();
+            assert_eq!(id_token.expose_secret(), "id-token");
+            assert_eq!(config.active_key_id().as_deref(), Some("key"));
+        })
+        .await
+        .unwrap();
+        let requests = server.requests();
+        assert_eq!(requests.len(), 5);
+        assert_eq!(
+            assertion_claims(&requests[3])["scope"],
+            Usage::CloudPlatform.as_string()
+        );
+        assert_eq!(
+            assertion_claims(&requests[4])["target_audience"],
+            "https://example.com"
+        );
+    }
+}
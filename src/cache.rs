@@ -0,0 +1,175 @@
+use crate::error::Result;
+use crate::provider::AccessToken;
+use chrono::{DateTime, Duration, Utc};
+use std::future::Future;
+use tokio::sync::Mutex;
+
+/// Tokens are refreshed this long before they expire,
+/// so a returned token is still valid when it reaches the google api services.
+static REFRESH_MARGIN_SECONDS: i64 = 300;
+
+/// Buffers one authentication token and refreshes it shortly before it expires.
+///
+/// Concurrent callers wait for a single refresh instead of requesting several tokens at once.
+#[allow(clippy::nursery)]
+pub(crate) struct TokenCache {
+    token: Mutex<Option<AccessToken>>,
+}
+
+impl TokenCache {
+    pub(crate) const fn new() -> Self {
+        Self {
+            token: Mutex::const_new(None),
+        }
+    }
+
+    /// Returns the buffered token or replaces it with the result of `refresh`
+    /// if there is none or it expires within the refresh margin after `now`.
+    /// The lock is held during the refresh, so concurrent callers reuse its result.
+    #[allow(clippy::significant_drop_tightening)]
+    pub(crate) async fn get_or_refresh<F, Fut>(
+        &self,
+        now: DateTime<Utc>,
+        refresh: F,
+    ) -> Result<AccessToken>
+    where
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<AccessToken>> + Send,
+    {
+        let mut token = self.token.lock().await;
+        if let Some(token) = token.as_ref().filter(|token| is_fresh(token, now)) {
+            return Ok(token.clone());
+        }
+        let fresh = refresh().await?;
+        *token = Some(fresh.clone());
+        Ok(fresh)
+    }
+
+    /// Replaces the buffered token with the result of `refresh`, even if it is still fresh.
+    #[allow(clippy::significant_drop_tightening)]
+    pub(crate) async fn force_refresh<F, Fut>(&self, refresh: F) -> Result<AccessToken>
+    where
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<AccessToken>> + Send,
+    {
+        let mut token = self.token.lock().await;
+        let fresh = refresh().await?;
+        *token = Some(fresh.clone());
+        Ok(fresh)
+    }
+}
+
+/// Works like [`TokenCache`] for the blocking api, the refresh blocks the other callers.
+#[cfg(feature = "blocking")]
+#[allow(clippy::nursery)]
+pub(crate) struct BlockingTokenCache {
+    token: std::sync::Mutex<Option<AccessToken>>,
+}
+
+#[cfg(feature = "blocking")]
+impl BlockingTokenCache {
+    pub(crate) const fn new() -> Self {
+        Self {
+            token: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Returns the buffered token or replaces it with the result of `refresh`
+    /// if there is none or it expires within the refresh margin after `now`.
+    #[allow(clippy::significant_drop_tightening)]
+    pub(crate) fn get_or_refresh<F>(&self, now: DateTime<Utc>, refresh: F) -> Result<AccessToken>
+    where
+        F: FnOnce() -> Result<AccessToken>,
+    {
+        // A panicking refresh never stores a token, so a poisoned lock holds a consistent value.
+        let mut token = self
+            .token
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(token) = token.as_ref().filter(|token| is_fresh(token, now)) {
+            return Ok(token.clone());
+        }
+        let fresh = refresh()?;
+        *token = Some(fresh.clone());
+        Ok(fresh)
+    }
+}
+
+fn is_fresh(token: &AccessToken, now: DateTime<Utc>) -> bool {
+    token.expires_at() - Duration::seconds(REFRESH_MARGIN_SECONDS) > now
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    #[tokio::test]
+    async fn test_get_or_refresh() {
+        let start = DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let token = |name: &str, expires_in| {
+            let token = AccessToken::expiring_in(name.to_string(), expires_in, start);
+            async move { Ok(token) }
+        };
+
+        let cache = TokenCache::new();
+        let first = cache
+            .get_or_refresh(start, || token("first", 3600))
+            .await
+            .unwrap();
+        assert_eq!(first.as_str(), "first");
+        let cached = cache
+            .get_or_refresh(start + Duration::seconds(3299), || token("second", 3600))
+            .await
+            .unwrap();
+        assert_eq!(cached.as_str(), "first");
+        let refreshed = cache
+            .get_or_refresh(start + Duration::seconds(3300), || token("second", 3600))
+            .await
+            .unwrap();
+        assert_eq!(refreshed.as_str(), "second");
+        let forced = cache.force_refresh(|| token("third", 3600)).await.unwrap();
+        assert_eq!(forced.as_str(), "third");
+        let cached = cache
+            .get_or_refresh(start, || token("fourth", 3600))
+            .await
+            .unwrap();
+        assert_eq!(cached.as_str(), "third");
+
+        let cache = TokenCache::new();
+        cache
+            .get_or_refresh(start, || token("first", 60))
+            .await
+            .unwrap();
+        let refreshed = cache
+            .get_or_refresh(start, || token("second", 3600))
+            .await
+            .unwrap();
+        assert_eq!(refreshed.as_str(), "second");
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_refresh() {
+        let cache = TokenCache::new();
+        let cancelled = tokio::time::timeout(
+            std::time::Duration::from_millis(10),
+            cache.get_or_refresh(Utc::now(), std::future::pending),
+        )
+        .await;
+        assert!(cancelled.is_err());
+
+        let token = cache
+            .get_or_refresh(Utc::now(), || async {
+                Ok(AccessToken::expiring_in(
+                    String::from("fresh"),
+                    3600,
+                    Utc::now(),
+                ))
+            })
+            .await
+            .unwrap();
+        assert_eq!(token.as_str(), "fresh");
+    }
+}
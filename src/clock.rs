@@ -0,0 +1,21 @@
+use chrono::{DateTime, Utc};
+
+/// The source of the current time for the jwt claims and the expiry of buffered tokens.
+///
+/// The default [`SystemClock`] reads the clock of the host.
+/// A custom clock can correct a host clock that is known to be wrong,
+/// or return fixed times in tests.
+pub trait Clock: Send + Sync {
+    /// The current time.
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The clock of the host.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
@@ -0,0 +1,585 @@
+use crate::cache::TokenCache;
+use crate::clock::{Clock, SystemClock};
+use crate::error::{Result, TokenGenerationError};
+use crate::http_client::HttpClient;
+use crate::json_structs::{GoogleResponse, StsTokenResponseJson};
+use crate::map_timeout;
+use crate::provider::{AccessToken, TokenProvider};
+use crate::retry::RetryPolicy;
+use crate::secret::SecretString;
+use crate::universe::{check_endpoint, service_endpoint};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+static STS_TOKEN_PATH: &str = "/v1/token";
+static TOKEN_EXCHANGE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+static ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+static STORAGE_BUCKET_RESOURCE: &str = "//storage.googleapis.com/projects/_/buckets/";
+static ROLE_PREFIX: &str = "inRole:";
+static MAX_RULES: usize = 10;
+
+/// A [Credential Access Boundary](https://cloud.google.com/iam/docs/downscoping-short-lived-credentials)
+/// that restricts the permissions of a downscoped token to the listed resources.
+///
+/// Example for the serialized boundary:
+/// ```json
+/// {
+///     "accessBoundary": {
+///         "accessBoundaryRules": [
+///             {
+///                 "availableResource": "//storage.googleapis.com/projects/_/buckets/example-bucket",
+///                 "availablePermissions": ["inRole:roles/storage.objectViewer"],
+///                 "availabilityCondition": {
+///                     "expression": "resource.name.startsWith('projects/_/buckets/example-bucket/objects/customer-a')"
+///                 }
+///             }
+///         ]
+///     }
+/// }
+/// ```
+///
+/// Deserialized boundaries are validated like [`CredentialAccessBoundary::new`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", try_from = "CredentialAccessBoundaryJson")]
+pub struct CredentialAccessBoundary {
+    access_boundary: AccessBoundary,
+}
+
+/// The unvalidated form of a deserialized [`CredentialAccessBoundary`].
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CredentialAccessBoundaryJson {
+    access_boundary: AccessBoundary,
+}
+
+impl TryFrom<CredentialAccessBoundaryJson> for CredentialAccessBoundary {
+    type Error = TokenGenerationError;
+
+    fn try_from(json: CredentialAccessBoundaryJson) -> Result<Self> {
+        Self::new(json.access_boundary.access_boundary_rules)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct AccessBoundary {
+    access_boundary_rules: Vec<AccessBoundaryRule>,
+}
+
+/// A single rule of a [`CredentialAccessBoundary`].
+/// The downscoped token has at most the `available_permissions` on the `available_resource`,
+/// optionally only where the `availability_condition` holds.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessBoundaryRule {
+    available_resource: String,
+    available_permissions: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    availability_condition: Option<AvailabilityCondition>,
+}
+
+/// A [CEL](https://cloud.google.com/iam/docs/conditions-overview#cel) condition that further restricts an [`AccessBoundaryRule`],
+/// e.g. to objects with a name prefix.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AvailabilityCondition {
+    expression: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+impl CredentialAccessBoundary {
+    /// Creates a boundary with the provided rules. Between 1 and 10 rules are allowed.
+    /// # Errors
+    /// [`TokenGenerationError::InvalidAccessBoundary`] if the rules are out of range
+    /// or a permission is not of the form `inRole:ROLE`.
+    pub fn new(rules: Vec<AccessBoundaryRule>) -> Result<Self> {
+        let boundary = Self {
+            access_boundary: AccessBoundary {
+                access_boundary_rules: rules,
+            },
+        };
+        boundary.validate()?;
+        Ok(boundary)
+    }
+
+    /// The rules of this boundary.
+    #[must_use]
+    pub fn rules(&self) -> &[AccessBoundaryRule] {
+        &self.access_boundary.access_boundary_rules
+    }
+
+    fn validate(&self) -> Result<()> {
+        let invalid = |reason: String| Err(TokenGenerationError::InvalidAccessBoundary(reason));
+        let rules = self.rules();
+        if rules.is_empty() || rules.len() > MAX_RULES {
+            return invalid(format!(
+                "{} rules provided, 1 to {MAX_RULES} are allowed.",
+                rules.len()
+            ));
+        }
+        for rule in rules {
+            if rule.available_resource.is_empty() {
+                return invalid(String::from("A rule has no available resource."));
+            }
+            if rule.available_permissions.is_empty() {
+                return invalid(format!(
+                    "The rule for '{}' has no available permissions.",
+                    rule.available_resource
+                ));
+            }
+            if let Some(permission) = rule
+                .available_permissions
+                .iter()
+                .find(|permission| !permission.starts_with(ROLE_PREFIX))
+            {
+                return invalid(format!(
+                    "The permission '{permission}' does not start with '{ROLE_PREFIX}'."
+                ));
+            }
+            if let Some(condition) = &rule.availability_condition {
+                if condition.expression.is_empty() {
+                    return invalid(format!(
+                        "The condition for '{}' has no expression.",
+                        rule.available_resource
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AccessBoundaryRule {
+    /// Creates a rule for any resource, e.g. `//storage.googleapis.com/projects/_/buckets/example-bucket`.
+    /// Roles can be passed with or without the `inRole:` prefix.
+    pub fn new<S: AsRef<str>>(available_resource: &str, available_roles: &[S]) -> Self {
+        Self {
+            available_resource: available_resource.to_string(),
+            available_permissions: available_roles
+                .iter()
+                .map(|role| {
+                    let role = role.as_ref();
+                    if role.starts_with(ROLE_PREFIX) {
+                        role.to_string()
+                    } else {
+                        format!("{ROLE_PREFIX}{role}")
+                    }
+                })
+                .collect(),
+            availability_condition: None,
+        }
+    }
+
+    /// Creates a rule for a Google Cloud Storage bucket.
+    pub fn for_bucket<S: AsRef<str>>(bucket: &str, available_roles: &[S]) -> Self {
+        Self::new(
+            &format!("{STORAGE_BUCKET_RESOURCE}{bucket}"),
+            available_roles,
+        )
+    }
+
+    /// Restricts the rule to the resources matching the condition.
+    #[must_use]
+    pub fn with_availability_condition(mut self, condition: AvailabilityCondition) -> Self {
+        self.availability_condition = Some(condition);
+        self
+    }
+
+    /// The resource this rule applies to.
+    #[must_use]
+    pub fn available_resource(&self) -> &str {
+        &self.available_resource
+    }
+
+    /// The permissions (`inRole:ROLE`) that are available on the resource.
+    #[must_use]
+    pub fn available_permissions(&self) -> &[String] {
+        &self.available_permissions
+    }
+
+    /// The optional condition of this rule.
+    #[must_use]
+    pub const fn availability_condition(&self) -> Option<&AvailabilityCondition> {
+        self.availability_condition.as_ref()
+    }
+}
+
+impl AvailabilityCondition {
+    /// Creates a condition from a CEL expression.
+    #[must_use]
+    pub fn new(expression: &str) -> Self {
+        Self {
+            expression: expression.to_string(),
+            title: None,
+            description: None,
+        }
+    }
+
+    /// Creates a condition that only matches the objects of `bucket` whose name starts with `prefix`.
+    #[must_use]
+    pub fn object_prefix(bucket: &str, prefix: &str) -> Self {
+        Self::new(&format!(
+            "resource.name.startsWith('projects/_/buckets/{bucket}/objects/{prefix}')"
+        ))
+    }
+
+    /// Sets a short title of the condition.
+    #[must_use]
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    /// Sets a description of the condition.
+    #[must_use]
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// The CEL expression of this condition.
+    #[must_use]
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+}
+
+/// This struct exchanges the tokens of a source credential for downscoped tokens
+/// that are restricted by a [`CredentialAccessBoundary`].
+///
+/// The downscoped token is buffered and refreshed shortly before it expires,
+/// together with a fresh token of the source credential.
+/// This structure is intended to be reused by the client for several token generation requests.
+pub struct DownscopedCredentials {
+    source: Box<dyn TokenProvider>,
+    boundary: CredentialAccessBoundary,
+    token_url: String,
+    retry_policy: RetryPolicy,
+    http: HttpClient,
+    cache: TokenCache,
+    clock: Arc<dyn Clock>,
+}
+
+impl DownscopedCredentials {
+    /// This function generates a downscoping configuration with the provided information.
+    /// # Params
+    /// **`source`: `TokenProvider`**<br>
+    /// The credential whose tokens get downscoped, e.g. an [`crate::AuthConfig`] with the `CloudPlatform` usage.
+    ///
+    /// **`boundary`: `CredentialAccessBoundary`**<br>
+    /// The upper bound of the permissions of the downscoped tokens.
+    pub fn build(source: impl TokenProvider + 'static, boundary: CredentialAccessBoundary) -> Self {
+        let token_url = format!(
+            "{}{STS_TOKEN_PATH}",
+            service_endpoint("sts", source.universe_domain())
+        );
+        Self {
+            source: Box::new(source),
+            boundary,
+            token_url,
+            retry_policy: RetryPolicy::default(),
+            http: HttpClient::default(),
+            cache: TokenCache::new(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Replaces the url of the Google security token service.
+    /// # Errors
+    /// A url outside the universe domain of the source credential results in [`TokenGenerationError::UniverseDomainMismatch`].
+    pub fn with_token_url(mut self, token_url: &str) -> Result<Self> {
+        check_endpoint(token_url, self.source.universe_domain())?;
+        self.token_url = token_url.to_string();
+        Ok(self)
+    }
+
+    /// Replaces the retry policy of failed token exchanges, by default [`RetryPolicy::default`].
+    #[must_use]
+    pub const fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Limits the time to establish a connection to the security token service, by default 10 seconds.
+    /// `None` waits as long as the operating system does.
+    #[must_use]
+    pub fn with_connect_timeout(mut self, connect_timeout: Option<Duration>) -> Self {
+        self.http = self.http.with_connect_timeout(connect_timeout);
+        self
+    }
+
+    /// Limits the time of a single token exchange, by default 30 seconds. `None` disables the limit.
+    #[must_use]
+    pub fn with_request_timeout(mut self, request_timeout: Option<Duration>) -> Self {
+        self.http = self.http.with_request_timeout(request_timeout);
+        self
+    }
+
+    /// Replaces the clock of the token expiry, by default [`SystemClock`].
+    #[must_use]
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Returns a buffered downscoped token or requests a new one if it expires soon.
+    /// # Errors
+    /// See [`crate::Error`] for a more detailed answer.
+    /// # Returns
+    /// The downscoped `auth_token` as String.
+    pub async fn generate_auth_token(&self) -> Result<SecretString> {
+        Ok(self.access_token().await?.into_secret())
+    }
+
+    /// Exchanges the provided token for a downscoped token without buffering it.
+    /// # Errors
+    /// See [`crate::Error`] for a more detailed answer.
+    pub async fn downscope(&self, source_token: &AccessToken) -> Result<AccessToken> {
+        let options = serde_json::to_string(&self.boundary)?;
+        let params = [
+            ("grant_type", TOKEN_EXCHANGE_GRANT_TYPE),
+            ("subject_token_type", ACCESS_TOKEN_TYPE),
+            ("requested_token_type", ACCESS_TOKEN_TYPE),
+            ("subject_token", source_token.as_str()),
+            ("options", &options),
+        ];
+        let resp = self
+            .retry_policy
+            .run(|| async {
+                let resp = self
+                    .http
+                    .get()?
+                    .post(&self.token_url)
+                    .form(&params)
+                    .send()
+                    .await
+                    .map_err(map_timeout)?;
+                GoogleResponse::<StsTokenResponseJson>::read(resp)
+                    .await
+                    .map_err(map_timeout)
+            })
+            .await?;
+        Ok(match resp.expires_in {
+            Some(expires_in) => {
+                AccessToken::expiring_in(resp.access_token, expires_in, self.clock.now())
+            }
+            None => AccessToken::new(resp.access_token, source_token.expires_at()),
+        })
+    }
+}
+
+#[async_trait]
+impl TokenProvider for DownscopedCredentials {
+    async fn access_token(&self) -> Result<AccessToken> {
+        self.cache
+            .get_or_refresh(self.clock.now(), || async {
+                let source_token = self.source.access_token().await?;
+                self.downscope(&source_token).await
+            })
+            .await
+    }
+
+    /// The source token is refreshed as well, the rejected token may have been derived from a revoked one.
+    async fn refresh_access_token(&self) -> Result<AccessToken> {
+        self.cache
+            .force_refresh(|| async {
+                let source_token = self.source.refresh_access_token().await?;
+                self.downscope(&source_token).await
+            })
+            .await
+    }
+
+    fn universe_domain(&self) -> &str {
+        self.source.universe_domain()
+    }
+
+    fn quota_project_id(&self) -> Option<&str> {
+        self.source.quota_project_id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::test_server::{TestResponse, TestServer};
+    use chrono::{DateTime, Utc};
+
+    /// Hands out `source-token` with a fixed expiry, refreshes hand out `refreshed-source-token`.
+    struct SourceToken {
+        expires_at: DateTime<Utc>,
+    }
+
+    #[async_trait]
+    impl TokenProvider for SourceToken {
+        async fn access_token(&self) -> Result<AccessToken> {
+            Ok(AccessToken::new(
+                String::from("source-token"),
+                self.expires_at,
+            ))
+        }
+
+        async fn refresh_access_token(&self) -> Result<AccessToken> {
+            Ok(AccessToken::new(
+                String::from("refreshed-source-token"),
+                self.expires_at,
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_downscope() {
+        let server = TestServer::start(vec![
+            TestResponse::json(503, "unavailable"),
+            TestResponse::json(
+                200,
+                r#"{"access_token": "downscoped-1", "issued_token_type": "urn:ietf:params:oauth:token-type:access_token", "token_type": "Bearer"}"#,
+            ),
+            TestResponse::token("downscoped-2"),
+        ])
+        .await;
+        let expires_at = Utc::now() + chrono::Duration::seconds(1800);
+        let source = SourceToken { expires_at };
+        let boundary = CredentialAccessBoundary::new(vec![AccessBoundaryRule::for_bucket(
+            "bucket",
+            &["roles/storage.objectViewer"],
+        )])
+        .unwrap();
+        let credentials = DownscopedCredentials::build(source, boundary.clone())
+            .with_token_url(&format!("{}/v1/token", server.url))
+            .unwrap()
+            .with_retry_policy(RetryPolicy::default().with_base_delay(Duration::from_millis(1)));
+
+        // Without `expires_in` the downscoped token expires together with the source token.
+        let token = credentials.access_token().await.unwrap();
+        assert_eq!(token.as_str(), "downscoped-1");
+        assert_eq!(token.expires_at(), expires_at);
+        assert_eq!(
+            credentials.access_token().await.unwrap().as_str(),
+            "downscoped-1"
+        );
+
+        let token = credentials.refresh_access_token().await.unwrap();
+        assert_eq!(token.as_str(), "downscoped-2");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        let form = |request: &str| -> Vec<(String, String)> {
+            let body = request.split("\r\n\r\n").nth(1).unwrap();
+            url::form_urlencoded::parse(body.as_bytes())
+                .into_owned()
+                .collect()
+        };
+        let params = form(&requests[1]);
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+                .unwrap()
+        };
+        assert_eq!(param("grant_type"), TOKEN_EXCHANGE_GRANT_TYPE);
+        assert_eq!(param("subject_token_type"), ACCESS_TOKEN_TYPE);
+        assert_eq!(param("requested_token_type"), ACCESS_TOKEN_TYPE);
+        assert_eq!(param("subject_token"), "source-token");
+        assert_eq!(
+            serde_json::from_str::<CredentialAccessBoundary>(&param("options")).unwrap(),
+            boundary
+        );
+        assert!(form(&requests[2]).contains(&(
+            String::from("subject_token"),
+            String::from("refreshed-source-token")
+        )));
+    }
+
+    #[test]
+    fn test_token_url_universe_domain() {
+        let boundary = CredentialAccessBoundary::new(vec![AccessBoundaryRule::for_bucket(
+            "bucket",
+            &["roles/storage.objectViewer"],
+        )])
+        .unwrap();
+        let source = SourceToken {
+            expires_at: Utc::now(),
+        };
+        assert!(matches!(
+            DownscopedCredentials::build(source, boundary)
+                .with_token_url("https://sts.example-cloud.eu/v1/token"),
+            Err(TokenGenerationError::UniverseDomainMismatch(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_serialize_boundary() {
+        let boundary = CredentialAccessBoundary::new(vec![AccessBoundaryRule::for_bucket(
+            "example-bucket",
+            &["roles/storage.objectViewer"],
+        )
+        .with_availability_condition(
+            AvailabilityCondition::object_prefix("example-bucket", "customer-a")
+                .with_title("customer-a"),
+        )])
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&boundary).unwrap(),
+            serde_json::json!({
+                "accessBoundary": {
+                    "accessBoundaryRules": [{
+                        "availableResource": "//storage.googleapis.com/projects/_/buckets/example-bucket",
+                        "availablePermissions": ["inRole:roles/storage.objectViewer"],
+                        "availabilityCondition": {
+                            "expression": "resource.name.startsWith('projects/_/buckets/example-bucket/objects/customer-a')",
+                            "title": "customer-a"
+                        }
+                    }]
+                }
+            })
+        );
+        let json = serde_json::to_string(&boundary).unwrap();
+        assert_eq!(
+            serde_json::from_str::<CredentialAccessBoundary>(&json).unwrap(),
+            boundary
+        );
+    }
+
+    #[test]
+    fn test_invalid_boundary() {
+        let rule = || AccessBoundaryRule::for_bucket("bucket", &["roles/storage.objectViewer"]);
+        assert!(CredentialAccessBoundary::new(vec![]).is_err());
+        assert!(CredentialAccessBoundary::new((0..11).map(|_| rule()).collect()).is_err());
+        assert!(
+            CredentialAccessBoundary::new(vec![AccessBoundaryRule::for_bucket(
+                "bucket",
+                &[] as &[&str]
+            )])
+            .is_err()
+        );
+        assert!(CredentialAccessBoundary::new(vec![
+            rule().with_availability_condition(AvailabilityCondition::new(""))
+        ])
+        .is_err());
+        assert!(CredentialAccessBoundary::new(vec![rule()]).is_ok());
+
+        let from_json = |rules: serde_json::Value| {
+            serde_json::from_value::<CredentialAccessBoundary>(
+                serde_json::json!({ "accessBoundary": { "accessBoundaryRules": rules } }),
+            )
+        };
+        assert!(from_json(serde_json::json!([])).is_err());
+        assert!(from_json(serde_json::json!([{
+            "availableResource": "//storage.googleapis.com/projects/_/buckets/bucket",
+            "availablePermissions": ["roles/storage.objectViewer"]
+        }]))
+        .is_err());
+        assert!(from_json(serde_json::json!([{
+            "availableResource": "//storage.googleapis.com/projects/_/buckets/bucket",
+            "availablePermissions": ["inRole:roles/storage.objectViewer"]
+        }]))
+        .is_ok());
+    }
+}
@@ -13,12 +13,337 @@ pub enum TokenGenerationError {
     #[error("JsonWebTokenError occurred: {0}")]
     JsonWebTokenError(#[from] jsonwebtoken::errors::Error),
     /// Reqwest library error
+    #[cfg(feature = "http")]
     #[error("ReqwestError occurred: {0}")]
     ReqwestError(#[from] reqwest::Error),
     /// Serde library error
     #[error("SerdeError occurred: {0}")]
     SerdeError(#[from] serde_json::Error),
-    /// The authentication service responded with an error
-    #[error("The authentication service returned an error:\nType: {0}, Message: {1}")]
-    AuthenticationError(String, String),
+    /// The authentication service responded with an error, see [`TokenGenerationError::kind`] for its meaning
+    #[error(
+        "The authentication service returned an error:\nType: {error}, Message: {description}"
+    )]
+    AuthenticationError {
+        /// The error code, e.g. `invalid_grant`, or the status of an IAM error, e.g. `PERMISSION_DENIED`
+        error: String,
+        /// The human readable description of the service
+        description: String,
+        /// The HTTP status of the response
+        status: Option<u16>,
+    },
+    /// Invalid lifetime of an impersonated token
+    #[error("The provided lifetime '{0}' is out of range 30..43200.")]
+    InvalidImpersonationLifetime(i64),
+    /// The service account impersonation url does not name a target service account
+    #[error("The service account impersonation url '{0}' is invalid.")]
+    InvalidImpersonationUrl(String),
+    /// The `type` of a credential JSON is not supported
+    #[error("The credential type '{0}' is not supported.")]
+    UnsupportedCredentialType(String),
+    /// The private key of the service account cannot be used for signing
+    #[error("The private key is invalid: {0}")]
+    InvalidPrivateKey(#[from] PrivateKeyError),
+    /// The keys of a rotating configuration do not belong to the same service account
+    #[error("The provided keys are inconsistent: {0}")]
+    InconsistentKeys(String),
+    /// A custom signing backend failed
+    #[error("The signer failed: {0}")]
+    SignerError(String),
+    /// The credential access boundary of a downscoped token is invalid
+    #[error("The credential access boundary is invalid: {0}")]
+    InvalidAccessBoundary(String),
+    /// The credential source of an external account is not supported
+    #[error("The credential source '{0}' is not supported.")]
+    UnsupportedCredentialSource(String),
+    /// A required field of the service account file is missing
+    #[error("The service account file misses the required field '{0}'.")]
+    MissingField(String),
+    /// A field of the service account file is not a string
+    #[error("The field '{0}' of the service account file must be a string.")]
+    InvalidFieldType(String),
+    /// The `client_email` of the service account file is no email address
+    #[error("The client_email '{0}' of the service account file is no valid email address.")]
+    InvalidClientEmail(String),
+    /// The `token_uri` of the service account file is no https url
+    #[error("The token_uri '{0}' of the service account file is no valid https url.")]
+    InvalidTokenUri(String),
+    /// The service responded with `429` or a `5xx` status, optionally with the delay of its `Retry-After` header.
+    /// The body is truncated, e.g. the HTML error page of a proxy.
+    #[error("The service is temporarily unavailable, status: {0}, body: {2}")]
+    ServiceUnavailable(u16, Option<std::time::Duration>, String),
+    /// The service responded with an unexpected status or body, e.g. an HTML error page of a proxy.
+    /// The body is truncated.
+    #[error("The service responded with status {0} and an unexpected body: {1}")]
+    UnexpectedResponse(u16, String),
+    /// The connect timeout, request timeout or deadline of a token request elapsed
+    #[error("The token request timed out: {0}")]
+    Timeout(String),
+    /// An endpoint belongs to another universe domain than the credential
+    #[error("The endpoint '{0}' does not belong to the universe domain '{1}'.")]
+    UniverseDomainMismatch(String, String),
+    /// The AWS region or security credentials could not be determined
+    #[error("The AWS environment could not be resolved: {0}")]
+    AwsMetadataError(String),
+    /// The AWS credential source lacks a url or variable to look up the region or security credentials
+    #[error("The AWS credential source is incomplete: {0}")]
+    AwsConfigurationError(String),
+    /// A token or quota project contains characters that are not allowed in http headers
+    #[error("The value of the '{0}' header is invalid.")]
+    InvalidHeaderValue(String),
+}
+
+impl TokenGenerationError {
+    /// Classifies the error, so callers can branch on its cause instead of parsing messages.
+    #[must_use]
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::AuthenticationError {
+                error, description, ..
+            } => classify_service_error(error, description),
+            Self::ServiceUnavailable(429, _, _) => ErrorKind::RateLimited,
+            Self::ServiceUnavailable(_, _, _) => ErrorKind::Unavailable,
+            Self::Timeout(_) => ErrorKind::Timeout,
+            Self::UnexpectedResponse(status, _) if (200..300).contains(status) => ErrorKind::Parse,
+            Self::UnexpectedResponse(429, _) => ErrorKind::RateLimited,
+            Self::UnexpectedResponse(401 | 403, _) => ErrorKind::PermissionDenied,
+            Self::UnexpectedResponse(_, _) => ErrorKind::Rejected,
+            #[cfg(feature = "http")]
+            Self::ReqwestError(error) if error.is_decode() => ErrorKind::Parse,
+            #[cfg(feature = "http")]
+            Self::ReqwestError(error) if error.is_connect() || error.is_request() => {
+                ErrorKind::Transport
+            }
+            #[cfg(feature = "http")]
+            Self::ReqwestError(error) if error.is_timeout() => ErrorKind::Timeout,
+            Self::AwsMetadataError(_) => ErrorKind::Transport,
+            Self::SerdeError(_) => ErrorKind::Parse,
+            Self::JsonWebTokenError(_) | Self::SignerError(_) => ErrorKind::Signing,
+            Self::InvalidLifetime(_)
+            | Self::InvalidImpersonationLifetime(_)
+            | Self::InvalidImpersonationUrl(_)
+            | Self::UnsupportedCredentialType(_)
+            | Self::InvalidPrivateKey(_)
+            | Self::InconsistentKeys(_)
+            | Self::InvalidAccessBoundary(_)
+            | Self::UnsupportedCredentialSource(_)
+            | Self::AwsConfigurationError(_)
+            | Self::MissingField(_)
+            | Self::InvalidFieldType(_)
+            | Self::InvalidClientEmail(_)
+            | Self::InvalidTokenUri(_)
+            | Self::UniverseDomainMismatch(_, _)
+            | Self::InvalidHeaderValue(_) => ErrorKind::Configuration,
+            #[cfg(feature = "http")]
+            Self::ReqwestError(_) => ErrorKind::Other,
+        }
+    }
+
+    /// Whether repeating the request may succeed, see [`ErrorKind::is_retryable`].
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        self.kind().is_retryable()
+    }
+
+    /// The HTTP status of the failed response, if the service answered.
+    #[must_use]
+    #[cfg_attr(not(feature = "http"), allow(clippy::missing_const_for_fn))]
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Self::AuthenticationError { status, .. } => *status,
+            Self::ServiceUnavailable(status, _, _) | Self::UnexpectedResponse(status, _) => {
+                Some(*status)
+            }
+            #[cfg(feature = "http")]
+            Self::ReqwestError(error) => error.status().map(|status| status.as_u16()),
+            _ => None,
+        }
+    }
+
+    /// A hint how to fix the cause of the error, see [`ErrorKind::remediation`].
+    #[must_use]
+    pub fn remediation(&self) -> &'static str {
+        self.kind().remediation()
+    }
+}
+
+/// The cause of a [`TokenGenerationError`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// `invalid_grant` with an invalid signature: the key got deleted or disabled in the Google console
+    DeletedKey,
+    /// `invalid_grant` because `iat`/`exp` are out of range: the clock of the host is wrong
+    ClockSkew,
+    /// `invalid_grant` because the service account is disabled or deleted
+    DisabledAccount,
+    /// Any other `invalid_grant`
+    InvalidGrant,
+    /// `invalid_scope`: the requested scope is unknown or not allowed
+    InvalidScope,
+    /// `unauthorized_client`: the domain-wide delegation of the client is missing
+    UnauthorizedClient,
+    /// The source credential lacks a permission, e.g. `PERMISSION_DENIED` of the IAM credentials api
+    PermissionDenied,
+    /// `429` or a quota error of the service
+    RateLimited,
+    /// `5xx` or a temporary error of the service
+    Unavailable,
+    /// The connection failed or broke
+    Transport,
+    /// A connect timeout, request timeout or deadline elapsed
+    Timeout,
+    /// A response or file cannot be parsed
+    Parse,
+    /// The assertion cannot be signed
+    Signing,
+    /// The configuration or an input is invalid
+    Configuration,
+    /// Any other rejection of the service
+    Rejected,
+    /// Any other failure
+    Other,
+}
+
+impl ErrorKind {
+    /// Whether repeating the request may succeed without changing the configuration.
+    #[must_use]
+    pub const fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            Self::RateLimited | Self::Unavailable | Self::Transport | Self::Timeout
+        )
+    }
+
+    /// A hint how to fix the cause of the error.
+    #[must_use]
+    pub const fn remediation(self) -> &'static str {
+        match self {
+            Self::DeletedKey => "The key is no longer valid as it got deleted or disabled in the Google console. Generate a new key and replace the key file.",
+            Self::ClockSkew => "The clock of this host deviates from the Google servers. Synchronize it, e.g. with NTP, or backdate the issue time of the assertions.",
+            Self::DisabledAccount => "The service account is disabled or deleted. Enable it in the Google console or use another service account.",
+            Self::InvalidGrant => "The authentication service rejected the assertion. Check the key file and the service account.",
+            Self::InvalidScope => "The requested scope is unknown or not allowed for this service account. Check the usage.",
+            Self::UnauthorizedClient => "The client is not authorized for the request. Configure the domain-wide delegation of the service account in the admin console.",
+            Self::PermissionDenied => "The source credential lacks a permission, e.g. roles/iam.serviceAccountTokenCreator on the target service account.",
+            Self::RateLimited => "The quota of the service is exceeded. Retry later with backoff or request a higher quota.",
+            Self::Unavailable => "The service is temporarily unavailable. Retry later.",
+            Self::Transport => "The connection to the service failed. Check the network, proxy and firewall settings.",
+            Self::Timeout => "The service did not answer in time. Check the network or increase the timeouts.",
+            Self::Parse => "A response or file cannot be parsed. Check the file content or the proxy between this host and Google.",
+            Self::Signing => "The assertion cannot be signed. Check the private key or the signing backend.",
+            Self::Configuration => "The configuration is invalid. The error message names the field or input to fix.",
+            Self::Rejected => "The service rejected the request. The error message contains its reason.",
+            Self::Other => "An unexpected error occurred. The error message contains details.",
+        }
+    }
+}
+
+/// Maps the error codes of the OAuth 2.0 token endpoint and the statuses of the IAM apis to an [`ErrorKind`].
+fn classify_service_error(error: &str, description: &str) -> ErrorKind {
+    let description = description.to_ascii_lowercase();
+    match error {
+        "invalid_grant" if description.contains("signature") => ErrorKind::DeletedKey,
+        "invalid_grant"
+            if description.contains("timeframe")
+                || description.contains("iat")
+                || description.contains("short-lived") =>
+        {
+            ErrorKind::ClockSkew
+        }
+        "invalid_grant"
+            if description.contains("disabled") || description.contains("not found") =>
+        {
+            ErrorKind::DisabledAccount
+        }
+        "invalid_grant" => ErrorKind::InvalidGrant,
+        "invalid_scope" => ErrorKind::InvalidScope,
+        "unauthorized_client" => ErrorKind::UnauthorizedClient,
+        "PERMISSION_DENIED" | "access_denied" => ErrorKind::PermissionDenied,
+        "RESOURCE_EXHAUSTED" | "rate_limit_exceeded" => ErrorKind::RateLimited,
+        "temporarily_unavailable"
+        | "server_error"
+        | "internal_failure"
+        | "UNAVAILABLE"
+        | "INTERNAL" => ErrorKind::Unavailable,
+        _ => ErrorKind::Rejected,
+    }
+}
+
+/// This error names the problem of a private key that was rejected at build time.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PrivateKeyError {
+    /// The key has no `-----BEGIN ...-----` PEM header
+    #[error("The key is not PEM encoded.")]
+    NotPem,
+    /// The key is protected by a passphrase
+    #[error("The key is encrypted, an unencrypted RSA key is required.")]
+    Encrypted,
+    /// The key is not an RSA private key, e.g. an EC key or a certificate
+    #[error("The key is of type '{0}', an RSA private key is required.")]
+    WrongKeyType(String),
+    /// The PEM content cannot be decoded or is no valid RSA key
+    #[error("The key is malformed: {0}")]
+    Malformed(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authentication_error(error: &str, description: &str) -> TokenGenerationError {
+        TokenGenerationError::AuthenticationError {
+            error: error.to_string(),
+            description: description.to_string(),
+            status: Some(400),
+        }
+    }
+
+    #[test]
+    fn test_kind() {
+        let error = authentication_error("invalid_grant", "Invalid JWT Signature.");
+        assert_eq!(error.kind(), ErrorKind::DeletedKey);
+        assert_eq!(error.status(), Some(400));
+        assert!(!error.is_retryable());
+        assert_eq!(
+            authentication_error(
+                "invalid_grant",
+                "Invalid JWT: Token must be a short-lived token (60 minutes) and in a reasonable timeframe. Check your iat and exp values in the JWT claim."
+            )
+            .kind(),
+            ErrorKind::ClockSkew
+        );
+        assert_eq!(
+            authentication_error("invalid_grant", "Invalid grant: account not found").kind(),
+            ErrorKind::DisabledAccount
+        );
+        assert_eq!(
+            authentication_error("invalid_scope", "Invalid OAuth scope.").kind(),
+            ErrorKind::InvalidScope
+        );
+        assert_eq!(
+            authentication_error("unauthorized_client", "Client is unauthorized.").kind(),
+            ErrorKind::UnauthorizedClient
+        );
+        assert!(authentication_error("temporarily_unavailable", "").is_retryable());
+
+        let error = TokenGenerationError::ServiceUnavailable(429, None, String::new());
+        assert_eq!(error.kind(), ErrorKind::RateLimited);
+        assert_eq!(error.status(), Some(429));
+        assert!(error.is_retryable());
+        assert_eq!(
+            TokenGenerationError::InvalidLifetime(0).kind(),
+            ErrorKind::Configuration
+        );
+        assert!(!TokenGenerationError::InvalidLifetime(0)
+            .remediation()
+            .is_empty());
+        assert_eq!(
+            TokenGenerationError::AwsConfigurationError(String::new()).kind(),
+            ErrorKind::Configuration
+        );
+        assert_eq!(
+            TokenGenerationError::AwsMetadataError(String::new()).kind(),
+            ErrorKind::Transport
+        );
+    }
 }
@@ -0,0 +1,637 @@
+use crate::cache::TokenCache;
+use crate::error::{Result, TokenGenerationError};
+use crate::http_client::HttpClient;
+use crate::impersonated::generate_access_token;
+use crate::json_structs::{
+    AwsRequestHeaderJson, AwsSecurityCredentialsJson, AwsSubjectTokenJson, CredentialSourceJson,
+    ExternalAccountInfoJson, GoogleResponse, ValidResponse,
+};
+use crate::map_timeout;
+use crate::provider::{resolve_quota_project_id, AccessToken, TokenProvider};
+use crate::retry::RetryPolicy;
+use crate::secret::SecretString;
+use crate::sigv4::{AwsRequestSigner, AwsSecurityCredentials, AWS_URI_ENCODE};
+use crate::universe::{check_endpoint, DEFAULT_UNIVERSE_DOMAIN};
+use crate::usage::Usage;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use percent_encoding::utf8_percent_encode;
+use std::env;
+use std::time::Duration;
+
+static TOKEN_EXCHANGE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+static ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+static AWS_ENVIRONMENT_ID: &str = "aws1";
+static TARGET_RESOURCE_HEADER: &str = "x-goog-cloud-target-resource";
+static IMDSV2_TTL_HEADER: &str = "x-aws-ec2-metadata-token-ttl-seconds";
+static IMDSV2_TOKEN_HEADER: &str = "x-aws-ec2-metadata-token";
+static IMDSV2_SESSION_TTL: &str = "300";
+static REGION_PLACEHOLDER: &str = "{region}";
+static DEFAULT_LIFETIME: i64 = 3600;
+
+/// This struct contains all necessary information to exchange AWS credentials for a Google authentication token.
+///
+/// See [Workload Identity Federation](https://cloud.google.com/iam/docs/workload-identity-federation-with-other-clouds).
+/// Instead of an exported service account key, the workload proves its AWS identity
+/// with a `SigV4` signed `GetCallerIdentity` request that is handed to the Google security token service (STS).
+/// The token is buffered and refreshed shortly before it expires.
+/// This structure is intended to be reused by the client for several token generation requests.
+pub struct ExternalAccountConfig {
+    audience: String,
+    subject_token_type: String,
+    token_url: String,
+    scope: String,
+    impersonation_url: Option<String>,
+    impersonation_lifetime: i64,
+    credential_source: AwsCredentialSource,
+    universe_domain: String,
+    quota_project_id: Option<String>,
+    retry_policy: RetryPolicy,
+    http: HttpClient,
+    cache: TokenCache,
+}
+
+/// The AWS endpoints to look up the region and the security credentials.
+/// The environment variables `AWS_REGION`/`AWS_DEFAULT_REGION`, `AWS_ACCESS_KEY_ID`,
+/// `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` take precedence over these endpoints.
+#[allow(clippy::struct_field_names)]
+struct AwsCredentialSource {
+    region_url: Option<String>,
+    security_credentials_url: Option<String>,
+    regional_cred_verification_url: String,
+    imdsv2_session_token_url: Option<String>,
+}
+
+impl ExternalAccountConfig {
+    /// This function generates an external account configuration with the provided information.
+    /// # Params
+    /// **`external_account_json_str`: String**<br>
+    /// The credential configuration file of a workload identity pool provider
+    /// (`"type": "external_account"`). Only the AWS credential source (`"environment_id": "aws1"`) is supported.
+    /// If the file contains a `service_account_impersonation_url`, the federated identity
+    /// acts as that service account and the returned tokens belong to it.
+    ///
+    /// **`usage`: String**<br>
+    /// Each google api request requires individual permissions to be executed.
+    /// See here for more information: [Google Scopes](https://developers.google.com/identity/protocols/oauth2/scopes?hl=en).
+    /// # Errors
+    /// See [`crate::Error`] for a more detailed answer.
+    /// A `token_url` or `service_account_impersonation_url` outside the universe domain results in [`crate::Error::UniverseDomainMismatch`].
+    pub fn build(external_account_json_str: &str, usage: &Usage) -> Result<Self> {
+        let account_info: ExternalAccountInfoJson =
+            serde_json::from_str(external_account_json_str)?;
+        let universe_domain = account_info
+            .universe_domain
+            .unwrap_or_else(|| DEFAULT_UNIVERSE_DOMAIN.to_string());
+        check_endpoint(&account_info.token_url, &universe_domain)?;
+        if let Some(url) = &account_info.service_account_impersonation_url {
+            check_endpoint(url, &universe_domain)?;
+        }
+        Ok(Self {
+            audience: account_info.audience,
+            subject_token_type: account_info.subject_token_type,
+            token_url: account_info.token_url,
+            scope: usage.as_string(),
+            impersonation_url: account_info.service_account_impersonation_url,
+            impersonation_lifetime: account_info
+                .service_account_impersonation
+                .and_then(|impersonation| impersonation.token_lifetime_seconds)
+                .unwrap_or(DEFAULT_LIFETIME),
+            credential_source: AwsCredentialSource::build(account_info.credential_source)?,
+            universe_domain,
+            quota_project_id: resolve_quota_project_id(account_info.quota_project_id),
+            retry_policy: RetryPolicy::default(),
+            http: HttpClient::default(),
+            cache: TokenCache::new(),
+        })
+    }
+
+    /// Replaces the url of the AWS metadata endpoint that returns the availability zone.
+    #[must_use]
+    pub fn with_region_url(mut self, region_url: &str) -> Self {
+        self.credential_source.region_url = Some(region_url.to_string());
+        self
+    }
+
+    /// Replaces the url of the AWS metadata endpoint that lists the security credentials of the attached role.
+    #[must_use]
+    pub fn with_security_credentials_url(mut self, security_credentials_url: &str) -> Self {
+        self.credential_source.security_credentials_url =
+            Some(security_credentials_url.to_string());
+        self
+    }
+
+    /// Replaces the url of the AWS metadata endpoint that issues `IMDSv2` session tokens.
+    #[must_use]
+    pub fn with_imdsv2_session_token_url(mut self, imdsv2_session_token_url: &str) -> Self {
+        self.credential_source.imdsv2_session_token_url =
+            Some(imdsv2_session_token_url.to_string());
+        self
+    }
+
+    /// Replaces the url of the AWS `GetCallerIdentity` request that gets signed.
+    /// A `{region}` placeholder gets replaced by the resolved region.
+    #[must_use]
+    pub fn with_regional_cred_verification_url(mut self, verification_url: &str) -> Self {
+        self.credential_source.regional_cred_verification_url = verification_url.to_string();
+        self
+    }
+
+    /// Replaces the quota project of the credential file and the `GOOGLE_CLOUD_QUOTA_PROJECT` environment variable,
+    /// see [`TokenProvider::quota_project_id`].
+    #[must_use]
+    pub fn with_quota_project_id(mut self, quota_project_id: Option<&str>) -> Self {
+        self.quota_project_id = quota_project_id.map(str::to_string);
+        self
+    }
+
+    /// Replaces the url of the Google security token service.
+    /// # Errors
+    /// A url outside the universe domain of the credential file results in [`TokenGenerationError::UniverseDomainMismatch`].
+    pub fn with_token_url(mut self, token_url: &str) -> Result<Self> {
+        check_endpoint(token_url, &self.universe_domain)?;
+        self.token_url = token_url.to_string();
+        Ok(self)
+    }
+
+    /// Replaces the retry policy of failed requests, by default [`RetryPolicy::default`].
+    #[must_use]
+    pub const fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Limits the time to establish a connection to the AWS and Google endpoints, by default 10 seconds.
+    /// `None` waits as long as the operating system does.
+    #[must_use]
+    pub fn with_connect_timeout(mut self, connect_timeout: Option<Duration>) -> Self {
+        self.http = self.http.with_connect_timeout(connect_timeout);
+        self
+    }
+
+    /// Limits the time of a single request, by default 30 seconds. `None` disables the limit.
+    #[must_use]
+    pub fn with_request_timeout(mut self, request_timeout: Option<Duration>) -> Self {
+        self.http = self.http.with_request_timeout(request_timeout);
+        self
+    }
+
+    /// The AWS identity of the workload will be exchanged for an authentication token (short: `auth_token`) at Google.
+    /// This `auth_token` will be returned and is used for requesting several google api services.
+    /// The token is buffered, see [`TokenProvider::access_token`].
+    /// # Errors
+    /// See [`crate::Error`] for a more detailed answer.
+    /// # Returns
+    /// The above-mentioned `auth_token` as String.
+    pub async fn generate_auth_token(&self) -> Result<SecretString> {
+        Ok(self.access_token().await?.into_secret())
+    }
+
+    async fn request_access_token(&self) -> Result<AccessToken> {
+        self.retry_policy
+            .run(|| async {
+                self.exchange_token(self.http.get()?)
+                    .await
+                    .map_err(map_timeout)
+            })
+            .await
+    }
+
+    async fn exchange_token(&self, client: &reqwest::Client) -> Result<AccessToken> {
+        let subject_token = self
+            .credential_source
+            .subject_token(client, &self.audience, Utc::now())
+            .await?;
+
+        // An impersonated service account receives the requested scope, the federated token needs the iam scope.
+        let sts_scope = match self.impersonation_url {
+            Some(_) => Usage::CloudPlatform.as_string(),
+            None => self.scope.clone(),
+        };
+        let params = [
+            ("grant_type", TOKEN_EXCHANGE_GRANT_TYPE),
+            ("audience", &self.audience),
+            ("scope", &sts_scope),
+            ("requested_token_type", ACCESS_TOKEN_TYPE),
+            ("subject_token", &subject_token),
+            ("subject_token_type", &self.subject_token_type),
+        ];
+        let resp = client.post(&self.token_url).form(&params).send().await?;
+        let resp = GoogleResponse::<ValidResponse>::read(resp).await?;
+
+        match &self.impersonation_url {
+            Some(url) => {
+                generate_access_token(
+                    client,
+                    url,
+                    resp.access_token.expose_secret(),
+                    &[],
+                    &self.scope,
+                    self.impersonation_lifetime,
+                )
+                .await
+            }
+            None => Ok(AccessToken::expiring_in(
+                resp.access_token,
+                resp.expires_in,
+                Utc::now(),
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl TokenProvider for ExternalAccountConfig {
+    async fn access_token(&self) -> Result<AccessToken> {
+        self.cache
+            .get_or_refresh(Utc::now(), || self.request_access_token())
+            .await
+    }
+
+    async fn refresh_access_token(&self) -> Result<AccessToken> {
+        self.cache
+            .force_refresh(|| self.request_access_token())
+            .await
+    }
+
+    fn universe_domain(&self) -> &str {
+        &self.universe_domain
+    }
+
+    fn quota_project_id(&self) -> Option<&str> {
+        self.quota_project_id.as_deref()
+    }
+}
+
+impl AwsCredentialSource {
+    fn build(source: CredentialSourceJson) -> Result<Self> {
+        match source.environment_id.as_deref() {
+            Some(id) if id == AWS_ENVIRONMENT_ID => {}
+            Some(id) => return Err(TokenGenerationError::UnsupportedCredentialSource(id.into())),
+            None => {
+                return Err(TokenGenerationError::UnsupportedCredentialSource(
+                    String::from("file/url"),
+                ))
+            }
+        }
+        let regional_cred_verification_url =
+            source.regional_cred_verification_url.ok_or_else(|| {
+                TokenGenerationError::AwsConfigurationError(String::from(
+                    "The credential source has no regional_cred_verification_url.",
+                ))
+            })?;
+        Ok(Self {
+            region_url: source.region_url,
+            security_credentials_url: source.url,
+            regional_cred_verification_url,
+            imdsv2_session_token_url: source.imdsv2_session_token_url,
+        })
+    }
+
+    /// Resolves the region and security credentials and serializes
+    /// the signed `GetCallerIdentity` request as url encoded JSON.
+    async fn subject_token(
+        &self,
+        client: &reqwest::Client,
+        audience: &str,
+        now: DateTime<Utc>,
+    ) -> Result<String> {
+        let env_region = region_from_env();
+        let env_credentials = credentials_from_env();
+
+        let session_token = if env_region.is_none() || env_credentials.is_none() {
+            self.imdsv2_session_token(client).await?
+        } else {
+            None
+        };
+        let region = match env_region {
+            Some(region) => region,
+            None => self.region(client, session_token.as_deref()).await?,
+        };
+        let credentials = match env_credentials {
+            Some(credentials) => credentials,
+            None => {
+                self.security_credentials(client, session_token.as_deref())
+                    .await?
+            }
+        };
+        self.serialize_subject_token(audience, &region, &credentials, now)
+    }
+
+    fn serialize_subject_token(
+        &self,
+        audience: &str,
+        region: &str,
+        credentials: &AwsSecurityCredentials,
+        now: DateTime<Utc>,
+    ) -> Result<String> {
+        let url = self
+            .regional_cred_verification_url
+            .replace(REGION_PLACEHOLDER, region);
+        let signer = AwsRequestSigner {
+            credentials,
+            region,
+            service: "sts",
+        };
+        let headers = signer.sign("POST", &url, &[(TARGET_RESOURCE_HEADER, audience)], "", now)?;
+
+        let subject_token = AwsSubjectTokenJson {
+            url,
+            method: String::from("POST"),
+            headers: headers
+                .into_iter()
+                .map(|(key, value)| AwsRequestHeaderJson { key, value })
+                .collect(),
+        };
+        let json = serde_json::to_string(&subject_token)?;
+        Ok(utf8_percent_encode(&json, AWS_URI_ENCODE).to_string())
+    }
+
+    async fn imdsv2_session_token(&self, client: &reqwest::Client) -> Result<Option<String>> {
+        let Some(url) = &self.imdsv2_session_token_url else {
+            return Ok(None);
+        };
+        let resp = client
+            .put(url)
+            .header(IMDSV2_TTL_HEADER, IMDSV2_SESSION_TTL)
+            .send()
+            .await?;
+        Ok(Some(metadata_text(url, resp).await?))
+    }
+
+    async fn region(
+        &self,
+        client: &reqwest::Client,
+        session_token: Option<&str>,
+    ) -> Result<String> {
+        let url = self.region_url.as_deref().ok_or_else(|| {
+            TokenGenerationError::AwsConfigurationError(String::from(
+                "Neither AWS_REGION nor a region_url is available.",
+            ))
+        })?;
+        // The endpoint returns the availability zone, e.g. `us-east-1b`.
+        let mut region = metadata_get(client, url, session_token).await?;
+        region.pop();
+        Ok(region)
+    }
+
+    async fn security_credentials(
+        &self,
+        client: &reqwest::Client,
+        session_token: Option<&str>,
+    ) -> Result<AwsSecurityCredentials> {
+        let url = self.security_credentials_url.as_deref().ok_or_else(|| {
+            TokenGenerationError::AwsConfigurationError(String::from(
+                "Neither AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY nor a credentials url is available.",
+            ))
+        })?;
+        let role = metadata_get(client, url, session_token).await?;
+        let credentials: AwsSecurityCredentialsJson = serde_json::from_str(
+            &metadata_get(client, &format!("{url}/{role}"), session_token).await?,
+        )?;
+        Ok(AwsSecurityCredentials {
+            access_key_id: credentials.access_key_id,
+            secret_access_key: credentials.secret_access_key,
+            session_token: credentials.token,
+        })
+    }
+}
+
+async fn metadata_get(
+    client: &reqwest::Client,
+    url: &str,
+    session_token: Option<&str>,
+) -> Result<String> {
+    let mut request = client.get(url);
+    if let Some(token) = session_token {
+        request = request.header(IMDSV2_TOKEN_HEADER, token);
+    }
+    metadata_text(url, request.send().await?).await
+}
+
+async fn metadata_text(url: &str, resp: reqwest::Response) -> Result<String> {
+    if !resp.status().is_success() {
+        return Err(TokenGenerationError::AwsMetadataError(format!(
+            "'{url}' responded with status {}.",
+            resp.status()
+        )));
+    }
+    Ok(resp.text().await?.trim().to_string())
+}
+
+fn region_from_env() -> Option<String> {
+    env::var("AWS_REGION")
+        .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+        .ok()
+        .filter(|region| !region.is_empty())
+}
+
+fn credentials_from_env() -> Option<AwsSecurityCredentials> {
+    Some(AwsSecurityCredentials {
+        access_key_id: env::var("AWS_ACCESS_KEY_ID").ok()?,
+        secret_access_key: SecretString::from(env::var("AWS_SECRET_ACCESS_KEY").ok()?),
+        session_token: env::var("AWS_SESSION_TOKEN").ok(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::test_server::{TestResponse, TestServer};
+    use chrono::TimeZone;
+    use percent_encoding::percent_decode_str;
+    use std::collections::HashMap;
+    use std::fs;
+
+    fn get_config() -> ExternalAccountConfig {
+        ExternalAccountConfig::build(
+            &fs::read_to_string("tests/test-external-account-aws.json").unwrap(),
+            &Usage::CloudPlatform,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_build_unsupported_source() {
+        let json = r#"{
+            "audience": "//iam.googleapis.com/projects/1/locations/global/workloadIdentityPools/p/providers/p",
+            "subject_token_type": "urn:ietf:params:oauth:token-type:jwt",
+            "token_url": "https://sts.googleapis.com/v1/token",
+            "credential_source": { "file": "/var/run/token" }
+        }"#;
+        assert!(matches!(
+            ExternalAccountConfig::build(json, &Usage::CloudPlatform),
+            Err(TokenGenerationError::UnsupportedCredentialSource(_))
+        ));
+    }
+
+    #[test]
+    fn test_build_universe_domain() {
+        let mut json: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string("tests/test-external-account-aws.json").unwrap(),
+        )
+        .unwrap();
+        json["service_account_impersonation_url"] = "https://iamcredentials.example-cloud.eu/v1/projects/-/serviceAccounts/target@test.iam.gserviceaccount.com:generateAccessToken".into();
+        assert!(matches!(
+            ExternalAccountConfig::build(&json.to_string(), &Usage::CloudPlatform),
+            Err(TokenGenerationError::UniverseDomainMismatch(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_token_url_universe_domain() {
+        assert!(matches!(
+            get_config().with_token_url("https://sts.example-cloud.eu/v1/token"),
+            Err(TokenGenerationError::UniverseDomainMismatch(_, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_aws_flow() {
+        // The environment takes precedence over the metadata server.
+        if region_from_env().is_some() || credentials_from_env().is_some() {
+            return;
+        }
+        let text = |body: &str| TestResponse {
+            headers: vec![("content-type", String::from("text/plain"))],
+            ..TestResponse::json(200, body)
+        };
+        let server = TestServer::start(vec![
+            text("session-token"),
+            text("eu-west-1b"),
+            text("test-role"),
+            TestResponse::json(
+                200,
+                r#"{"AccessKeyId": "AKIDEXAMPLE", "SecretAccessKey": "secret", "Token": "session"}"#,
+            ),
+            TestResponse::token("federated-token"),
+        ])
+        .await;
+        let config = get_config()
+            .with_imdsv2_session_token_url(&format!("{}/latest/api/token", server.url))
+            .with_region_url(&format!(
+                "{}/latest/meta-data/placement/availability-zone",
+                server.url
+            ))
+            .with_security_credentials_url(&format!(
+                "{}/latest/meta-data/iam/security-credentials",
+                server.url
+            ))
+            .with_token_url(&format!("{}/v1/token", server.url))
+            .unwrap();
+
+        let token = config.generate_auth_token().await.unwrap();
+        assert_eq!(token.expose_secret(), "federated-token");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 5);
+        let head = |request: &str| request.lines().next().unwrap().to_string();
+        assert_eq!(head(&requests[0]), "PUT /latest/api/token HTTP/1.1");
+        assert!(requests[0].contains(&format!("{IMDSV2_TTL_HEADER}: {IMDSV2_SESSION_TTL}")));
+        assert_eq!(
+            head(&requests[1]),
+            "GET /latest/meta-data/placement/availability-zone HTTP/1.1"
+        );
+        assert_eq!(
+            head(&requests[2]),
+            "GET /latest/meta-data/iam/security-credentials HTTP/1.1"
+        );
+        assert_eq!(
+            head(&requests[3]),
+            "GET /latest/meta-data/iam/security-credentials/test-role HTTP/1.1"
+        );
+        for request in &requests[1..4] {
+            assert!(request.contains(&format!("{IMDSV2_TOKEN_HEADER}: session-token")));
+        }
+
+        assert_eq!(head(&requests[4]), "POST /v1/token HTTP/1.1");
+        let body = requests[4].split("\r\n\r\n").nth(1).unwrap();
+        let params: HashMap<String, String> = url::form_urlencoded::parse(body.as_bytes())
+            .into_owned()
+            .collect();
+        assert_eq!(params["grant_type"], TOKEN_EXCHANGE_GRANT_TYPE);
+        assert_eq!(params["audience"], config.audience);
+        assert_eq!(params["subject_token_type"], config.subject_token_type);
+        let subject_token: serde_json::Value = serde_json::from_str(
+            &percent_decode_str(&params["subject_token"])
+                .decode_utf8()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            subject_token["url"],
+            "https://sts.eu-west-1.amazonaws.com?Action=GetCallerIdentity&Version=2011-06-15"
+        );
+        let headers = subject_token["headers"].as_array().unwrap();
+        let header = |key: &str| {
+            headers
+                .iter()
+                .find(|header| header["key"] == key)
+                .map(|header| header["value"].as_str().unwrap().to_string())
+        };
+        assert_eq!(header("x-amz-security-token").unwrap(), "session");
+        assert!(header("authorization")
+            .unwrap()
+            .contains("Credential=AKIDEXAMPLE/"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_metadata_urls() {
+        let mut config = get_config();
+        config.credential_source.region_url = None;
+        config.credential_source.security_credentials_url = None;
+        let client = reqwest::Client::new();
+        let error = config
+            .credential_source
+            .region(&client, None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Configuration);
+        let error = config
+            .credential_source
+            .security_credentials(&client, None)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::Configuration);
+    }
+
+    #[test]
+    fn test_serialize_subject_token() {
+        let config = get_config();
+        let credentials = AwsSecurityCredentials {
+            access_key_id: String::from("AKIDEXAMPLE"),
+            secret_access_key: SecretString::from(String::from("secret")),
+            session_token: Some(String::from("session")),
+        };
+        let now = Utc.with_ymd_and_hms(2023, 10, 14, 12, 0, 0).unwrap();
+        let token = config
+            .credential_source
+            .serialize_subject_token(&config.audience, "eu-west-1", &credentials, now)
+            .unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&percent_decode_str(&token).decode_utf8().unwrap()).unwrap();
+        assert_eq!(
+            json["url"],
+            "https://sts.eu-west-1.amazonaws.com?Action=GetCallerIdentity&Version=2011-06-15"
+        );
+        assert_eq!(json["method"], "POST");
+        let headers = json["headers"].as_array().unwrap();
+        let header = |key: &str| {
+            headers
+                .iter()
+                .find(|header| header["key"] == key)
+                .map(|header| header["value"].as_str().unwrap().to_string())
+        };
+        assert_eq!(header("host").unwrap(), "sts.eu-west-1.amazonaws.com");
+        assert_eq!(header("x-amz-date").unwrap(), "20231014T120000Z");
+        assert_eq!(header("x-amz-security-token").unwrap(), "session");
+        assert_eq!(header(TARGET_RESOURCE_HEADER).unwrap(), config.audience);
+        assert!(header("authorization").unwrap().starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20231014/eu-west-1/sts/aws4_request"
+        ));
+    }
+}
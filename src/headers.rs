@@ -0,0 +1,122 @@
+use crate::error::{Result, TokenGenerationError};
+use crate::provider::{AccessToken, TokenProvider};
+use http::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+
+/// The name of the header that bills the request to a quota project.
+pub static USER_PROJECT: HeaderName = HeaderName::from_static("x-goog-user-project");
+/// The name of the header that identifies the client library.
+pub static API_CLIENT: HeaderName = HeaderName::from_static("x-goog-api-client");
+static API_CLIENT_VALUE: &str = concat!("gl-rust google-jwt-auth/", env!("CARGO_PKG_VERSION"));
+
+/// This struct holds the headers that authorize a google api request:
+/// `Authorization: Bearer`, the optional `x-goog-user-project` and `x-goog-api-client`.
+///
+/// The authorization header is marked as sensitive, so it is not logged by http libraries.
+#[derive(Clone)]
+pub struct AuthHeaders {
+    authorization: HeaderValue,
+    quota_project: Option<HeaderValue>,
+}
+
+impl AuthHeaders {
+    /// This function generates the headers of a token.
+    /// # Params
+    /// **`token`: `AccessToken`**<br>
+    /// The token sent as `Authorization: Bearer` header.
+    ///
+    /// **`quota_project_id`: Option<&str>**<br>
+    /// The project that is billed for the request, sent as `x-goog-user-project` header.
+    /// # Errors
+    /// Characters that are not allowed in http headers result in [`crate::Error::InvalidHeaderValue`].
+    pub fn build(token: &AccessToken, quota_project_id: Option<&str>) -> Result<Self> {
+        let mut authorization =
+            header_value(&AUTHORIZATION, &format!("Bearer {}", token.as_str()))?;
+        authorization.set_sensitive(true);
+        Ok(Self {
+            authorization,
+            quota_project: quota_project_id
+                .map(|quota_project_id| header_value(&USER_PROJECT, quota_project_id))
+                .transpose()?,
+        })
+    }
+
+    /// This function generates the headers of the buffered token and the quota project of the `provider`.
+    /// # Errors
+    /// See [`TokenProvider::access_token`] and [`AuthHeaders::build`].
+    pub async fn from_provider<P: TokenProvider + ?Sized>(provider: &P) -> Result<Self> {
+        Self::build(&provider.access_token().await?, provider.quota_project_id())
+    }
+
+    /// This function writes the headers into `headers`, existing values are replaced.
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        headers.insert(AUTHORIZATION, self.authorization.clone());
+        if let Some(quota_project) = &self.quota_project {
+            headers.insert(USER_PROJECT.clone(), quota_project.clone());
+        }
+        headers.insert(
+            API_CLIENT.clone(),
+            HeaderValue::from_static(API_CLIENT_VALUE),
+        );
+    }
+
+    /// This function writes the headers into an [`http::Request`].
+    pub fn apply_to_request<B>(&self, request: &mut http::Request<B>) {
+        self.apply(request.headers_mut());
+    }
+
+    /// This function adds the headers to a [`reqwest::RequestBuilder`].
+    pub fn apply_to_builder(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let mut headers = HeaderMap::new();
+        self.apply(&mut headers);
+        builder.headers(headers)
+    }
+}
+
+/// The `Debug` output never contains the token.
+impl std::fmt::Debug for AuthHeaders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthHeaders")
+            .field("quota_project", &self.quota_project)
+            .finish_non_exhaustive()
+    }
+}
+
+fn header_value(name: &HeaderName, value: &str) -> Result<HeaderValue> {
+    HeaderValue::from_str(value)
+        .map_err(|_| TokenGenerationError::InvalidHeaderValue(name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_apply() {
+        let token = AccessToken::expiring_in(String::from("token"), 3600, Utc::now());
+        let headers = AuthHeaders::build(&token, Some("billing-project")).unwrap();
+
+        let mut request = http::Request::new(());
+        headers.apply_to_request(&mut request);
+        assert_eq!(request.headers()[AUTHORIZATION], "Bearer token");
+        assert!(request.headers()[AUTHORIZATION].is_sensitive());
+        assert_eq!(request.headers()[&USER_PROJECT], "billing-project");
+        assert!(request.headers()[&API_CLIENT]
+            .to_str()
+            .unwrap()
+            .starts_with("gl-rust google-jwt-auth/"));
+
+        let request = headers
+            .apply_to_builder(reqwest::Client::new().get("http://localhost"))
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()[AUTHORIZATION], "Bearer token");
+        assert!(!format!("{headers:?}").contains("token"));
+
+        assert!(matches!(
+            AuthHeaders::build(&token, Some("billing\nproject")),
+            Err(TokenGenerationError::InvalidHeaderValue(_))
+        ));
+    }
+}
@@ -0,0 +1,58 @@
+use crate::error::Result;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+static DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+static DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The http client of a credential with its connect and request timeouts.
+///
+/// The client is built on the first request and shared by all later ones, so their connections are reused.
+/// Changing a timeout drops the built client.
+#[allow(clippy::nursery)]
+pub(crate) struct HttpClient {
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) request_timeout: Option<Duration>,
+    client: OnceLock<reqwest::Client>,
+}
+
+impl Default for HttpClient {
+    /// A connect timeout of 10 seconds and a request timeout of 30 seconds.
+    fn default() -> Self {
+        Self {
+            connect_timeout: Some(DEFAULT_CONNECT_TIMEOUT),
+            request_timeout: Some(DEFAULT_REQUEST_TIMEOUT),
+            client: OnceLock::new(),
+        }
+    }
+}
+
+#[allow(clippy::nursery)]
+impl HttpClient {
+    pub(crate) fn with_connect_timeout(mut self, connect_timeout: Option<Duration>) -> Self {
+        self.connect_timeout = connect_timeout;
+        self.client = OnceLock::new();
+        self
+    }
+
+    pub(crate) fn with_request_timeout(mut self, request_timeout: Option<Duration>) -> Self {
+        self.request_timeout = request_timeout;
+        self.client = OnceLock::new();
+        self
+    }
+
+    pub(crate) fn get(&self) -> Result<&reqwest::Client> {
+        if let Some(client) = self.client.get() {
+            return Ok(client);
+        }
+        let mut builder = reqwest::Client::builder();
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(request_timeout) = self.request_timeout {
+            builder = builder.timeout(request_timeout);
+        }
+        let client = builder.build()?;
+        Ok(self.client.get_or_init(|| client))
+    }
+}
@@ -0,0 +1,423 @@
+use crate::cache::TokenCache;
+use crate::error::{Result, TokenGenerationError};
+use crate::external_account::ExternalAccountConfig;
+use crate::http_client::HttpClient;
+use crate::json_structs::{
+    GenerateAccessTokenRequestJson, GenerateAccessTokenResponseJson, GenerateIdTokenRequestJson,
+    GenerateIdTokenResponseJson, IamResponse, ImpersonatedServiceAccountInfoJson,
+};
+use crate::provider::{AccessToken, TokenProvider};
+use crate::retry::RetryPolicy;
+use crate::secret::SecretString;
+use crate::universe::{check_endpoint, service_endpoint};
+use crate::usage::Usage;
+use crate::{map_timeout, AuthConfig};
+use async_trait::async_trait;
+use chrono::Utc;
+use std::time::Duration;
+
+pub(crate) static SERVICE_ACCOUNTS_PATH: &str = "/v1/projects/-/serviceAccounts/";
+static GENERATE_ACCESS_TOKEN: &str = ":generateAccessToken";
+static DEFAULT_LIFETIME: i64 = 3600;
+
+/// This struct contains all necessary information to act as a target service account.
+///
+/// A base credential (e.g. an [`AuthConfig`]) with the `roles/iam.serviceAccountTokenCreator` role on the target
+/// requests short-lived tokens for the target via the
+/// [IAM credentials api](https://cloud.google.com/iam/docs/reference/credentials/rest).
+/// The token of [`TokenProvider::access_token`] is buffered and refreshed shortly before it expires.
+/// This structure is intended to be reused by the client for several token generation requests.
+pub struct ImpersonatedCredentials {
+    source: Box<dyn TokenProvider>,
+    target_principal: String,
+    scope: String,
+    delegates: Vec<String>,
+    endpoint: String,
+    retry_policy: RetryPolicy,
+    http: HttpClient,
+    cache: TokenCache,
+}
+
+impl ImpersonatedCredentials {
+    /// This function generates an impersonation configuration with the provided information.
+    /// # Params
+    /// **`source`: `TokenProvider`**<br>
+    /// The base credential that is allowed to create tokens for the target service account.
+    /// Its scope must include `https://www.googleapis.com/auth/cloud-platform`.
+    ///
+    /// **`target_principal`: String**<br>
+    /// The email of the service account to act as.
+    ///
+    /// **`usage`: String**<br>
+    /// The scope of the tokens of the target service account.
+    /// See here for more information: [Google Scopes](https://developers.google.com/identity/protocols/oauth2/scopes?hl=en).
+    pub fn build(
+        source: impl TokenProvider + 'static,
+        target_principal: &str,
+        usage: &Usage,
+    ) -> Self {
+        let endpoint = service_endpoint("iamcredentials", source.universe_domain());
+        Self {
+            source: Box::new(source),
+            target_principal: target_principal.to_string(),
+            scope: usage.as_string(),
+            delegates: Vec::new(),
+            endpoint,
+            retry_policy: RetryPolicy::default(),
+            http: HttpClient::default(),
+            cache: TokenCache::new(),
+        }
+    }
+
+    /// This function generates an impersonation configuration from a `service_account_impersonation_url`
+    /// like it is found in credential JSON files, e.g.
+    /// `https://iamcredentials.googleapis.com/v1/projects/-/serviceAccounts/target@??????.iam.gserviceaccount.com:generateAccessToken`.
+    /// # Errors
+    /// See [`crate::Error`] for a more detailed answer.
+    pub fn build_from_url(
+        source: impl TokenProvider + 'static,
+        impersonation_url: &str,
+        usage: &Usage,
+    ) -> Result<Self> {
+        let (endpoint, target_principal) = parse_impersonation_url(impersonation_url)?;
+        check_endpoint(endpoint, source.universe_domain())?;
+        let mut credentials = Self::build(source, target_principal, usage);
+        credentials.endpoint = endpoint.to_string();
+        Ok(credentials)
+    }
+
+    /// This function generates an impersonation configuration from a credential JSON
+    /// with `"type": "impersonated_service_account"`.
+    /// The `source_credentials` must be a service account key or an external account configuration.
+    /// # Errors
+    /// See [`crate::Error`] for a more detailed answer.
+    pub fn build_from_json(impersonated_json_str: &str, usage: &Usage) -> Result<Self> {
+        let info: ImpersonatedServiceAccountInfoJson = serde_json::from_str(impersonated_json_str)?;
+        let source_json = info.source_credentials.to_string();
+        let credentials = match info.source_credentials["type"].as_str() {
+            Some("service_account") => Self::build_from_url(
+                AuthConfig::build(&source_json, &Usage::CloudPlatform)?,
+                &info.service_account_impersonation_url,
+                usage,
+            )?,
+            Some("external_account") => Self::build_from_url(
+                ExternalAccountConfig::build(&source_json, &Usage::CloudPlatform)?,
+                &info.service_account_impersonation_url,
+                usage,
+            )?,
+            other => {
+                return Err(TokenGenerationError::UnsupportedCredentialType(
+                    other.unwrap_or_default().to_string(),
+                ))
+            }
+        };
+        Ok(credentials.with_delegates(&info.delegates))
+    }
+
+    /// Sets the chain of service accounts that get impersonated one after another before the target.
+    /// Each service account must have the `roles/iam.serviceAccountTokenCreator` role on the next one,
+    /// the last one on the target. Emails and `projects/-/serviceAccounts/EMAIL` names are accepted.
+    #[must_use]
+    pub fn with_delegates<S: AsRef<str>>(mut self, delegates: &[S]) -> Self {
+        self.delegates = delegates
+            .iter()
+            .map(|delegate| resource_name(delegate.as_ref()))
+            .collect();
+        self
+    }
+
+    /// Replaces the retry policy of failed requests, by default [`RetryPolicy::default`].
+    #[must_use]
+    pub const fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Limits the time to establish a connection to the IAM credentials api, by default 10 seconds.
+    /// `None` waits as long as the operating system does.
+    #[must_use]
+    pub fn with_connect_timeout(mut self, connect_timeout: Option<Duration>) -> Self {
+        self.http = self.http.with_connect_timeout(connect_timeout);
+        self
+    }
+
+    /// Limits the time of a single request, by default 30 seconds. `None` disables the limit.
+    #[must_use]
+    pub fn with_request_timeout(mut self, request_timeout: Option<Duration>) -> Self {
+        self.http = self.http.with_request_timeout(request_timeout);
+        self
+    }
+
+    /// An authentication token (short: `auth_token`) of the target service account will be requested from Google.
+    /// # Params
+    /// **`lifetime`: i64**<br>
+    /// The lifetime in seconds, between 30 and 43200 (12 hours).
+    /// Lifetimes above 3600 require the `constraints/iam.allowServiceAccountCredentialLifetimeExtension` organization policy.
+    /// # Errors
+    /// See [`crate::Error`] for a more detailed answer.
+    /// # Returns
+    /// The above-mentioned `auth_token` as String.
+    pub async fn generate_auth_token(&self, lifetime: i64) -> Result<SecretString> {
+        Ok(self.request_access_token(lifetime).await?.into_secret())
+    }
+
+    /// An `OpenID Connect` id token of the target service account will be requested from Google.
+    /// # Params
+    /// **`audience`: String**<br>
+    /// The audience (`aud` claim) of the id token, usually the url of the receiving service.
+    ///
+    /// **`include_email`: bool**<br>
+    /// Whether the token contains the `email` and `email_verified` claims.
+    /// # Errors
+    /// See [`crate::Error`] for a more detailed answer.
+    /// # Returns
+    /// The above-mentioned id token as String.
+    pub async fn generate_id_token(
+        &self,
+        audience: &str,
+        include_email: bool,
+    ) -> Result<SecretString> {
+        self.retry_policy
+            .run(|| async {
+                let source_token = self.source.access_token().await?;
+                let resp = self
+                    .http
+                    .get()?
+                    .post(format!(
+                        "{}{SERVICE_ACCOUNTS_PATH}{}:generateIdToken",
+                        self.endpoint, self.target_principal
+                    ))
+                    .bearer_auth(source_token.as_str())
+                    .json(&GenerateIdTokenRequestJson {
+                        delegates: &self.delegates,
+                        audience,
+                        include_email,
+                    })
+                    .send()
+                    .await
+                    .map_err(map_timeout)?;
+                Ok(IamResponse::<GenerateIdTokenResponseJson>::read(resp)
+                    .await
+                    .map_err(map_timeout)?
+                    .token)
+            })
+            .await
+    }
+
+    async fn request_access_token(&self, lifetime: i64) -> Result<AccessToken> {
+        self.retry_policy
+            .run(|| async {
+                let source_token = self.source.access_token().await?;
+                generate_access_token(
+                    self.http.get()?,
+                    &format!(
+                        "{}{SERVICE_ACCOUNTS_PATH}{}{GENERATE_ACCESS_TOKEN}",
+                        self.endpoint, self.target_principal
+                    ),
+                    source_token.as_str(),
+                    &self.delegates,
+                    &self.scope,
+                    lifetime,
+                )
+                .await
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl TokenProvider for ImpersonatedCredentials {
+    /// The token is buffered and refreshed shortly before it expires.
+    async fn access_token(&self) -> Result<AccessToken> {
+        self.cache
+            .get_or_refresh(Utc::now(), || self.request_access_token(DEFAULT_LIFETIME))
+            .await
+    }
+
+    async fn refresh_access_token(&self) -> Result<AccessToken> {
+        self.cache
+            .force_refresh(|| self.request_access_token(DEFAULT_LIFETIME))
+            .await
+    }
+
+    fn universe_domain(&self) -> &str {
+        self.source.universe_domain()
+    }
+
+    fn quota_project_id(&self) -> Option<&str> {
+        self.source.quota_project_id()
+    }
+}
+
+/// Calls the `generateAccessToken` method at `url` authorized by `bearer`.
+pub(crate) async fn generate_access_token(
+    client: &reqwest::Client,
+    url: &str,
+    bearer: &str,
+    delegates: &[String],
+    scope: &str,
+    lifetime: i64,
+) -> Result<AccessToken> {
+    if !(30..=43200).contains(&lifetime) {
+        return Err(TokenGenerationError::InvalidImpersonationLifetime(lifetime));
+    }
+    let resp = client
+        .post(url)
+        .bearer_auth(bearer)
+        .json(&GenerateAccessTokenRequestJson {
+            delegates,
+            scope: scope.split_whitespace().collect(),
+            lifetime: format!("{lifetime}s"),
+        })
+        .send()
+        .await
+        .map_err(map_timeout)?;
+    let resp = IamResponse::<GenerateAccessTokenResponseJson>::read(resp)
+        .await
+        .map_err(map_timeout)?;
+    Ok(AccessToken::new(resp.access_token, resp.expire_time))
+}
+
+/// Splits an impersonation url into the api endpoint and the email of the target service account.
+fn parse_impersonation_url(url: &str) -> Result<(&str, &str)> {
+    let invalid = || TokenGenerationError::InvalidImpersonationUrl(url.to_string());
+    let (endpoint, _) = url.split_once("/v1/").ok_or_else(invalid)?;
+    let (_, target) = url.rsplit_once("/serviceAccounts/").ok_or_else(invalid)?;
+    let target = target
+        .strip_suffix(GENERATE_ACCESS_TOKEN)
+        .ok_or_else(invalid)?;
+    if target.is_empty() || endpoint.is_empty() {
+        return Err(invalid());
+    }
+    Ok((endpoint, target))
+}
+
+fn resource_name(service_account: &str) -> String {
+    if service_account.starts_with("projects/") {
+        service_account.to_string()
+    } else {
+        format!("projects/-/serviceAccounts/{service_account}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::test_server::{TestResponse, TestServer};
+    use std::fs;
+
+    struct SourceToken;
+
+    #[async_trait]
+    impl TokenProvider for SourceToken {
+        async fn access_token(&self) -> Result<AccessToken> {
+            Ok(AccessToken::expiring_in(
+                String::from("source-token"),
+                3600,
+                Utc::now(),
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_and_buffer() {
+        let server = TestServer::start(vec![
+            TestResponse::json(503, "unavailable"),
+            TestResponse::json(
+                200,
+                r#"{"accessToken": "target-token", "expireTime": "2099-01-01T00:00:00Z"}"#,
+            ),
+        ])
+        .await;
+        let credentials = ImpersonatedCredentials::build_from_url(
+            SourceToken,
+            &format!(
+                "{}/v1/projects/-/serviceAccounts/target@test.iam.gserviceaccount.com:generateAccessToken",
+                server.url
+            ),
+            &Usage::CloudPlatform,
+        )
+        .unwrap()
+        .with_retry_policy(RetryPolicy::default().with_base_delay(Duration::from_millis(1)));
+
+        assert_eq!(
+            credentials.access_token().await.unwrap().as_str(),
+            "target-token"
+        );
+        // The second call is served from the buffer, the server has no third response.
+        assert_eq!(
+            credentials.access_token().await.unwrap().as_str(),
+            "target-token"
+        );
+    }
+
+    #[test]
+    fn test_parse_impersonation_url() {
+        let (endpoint, target) = parse_impersonation_url(
+            "https://iamcredentials.googleapis.com/v1/projects/-/serviceAccounts/target@test.iam.gserviceaccount.com:generateAccessToken",
+        )
+        .unwrap();
+        assert_eq!(endpoint, "https://iamcredentials.googleapis.com");
+        assert_eq!(target, "target@test.iam.gserviceaccount.com");
+
+        assert!(parse_impersonation_url("https://iamcredentials.googleapis.com").is_err());
+        assert!(parse_impersonation_url(
+            "https://iamcredentials.googleapis.com/v1/projects/-/serviceAccounts/:generateAccessToken"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_with_delegates() {
+        let source = AuthConfig::build(
+            &fs::read_to_string("tests/invalid-value-client.json").unwrap(),
+            &Usage::CloudPlatform,
+        )
+        .unwrap();
+        let credentials = ImpersonatedCredentials::build(
+            source,
+            "target@test.iam.gserviceaccount.com",
+            &Usage::CloudVision,
+        )
+        .with_delegates(&[
+            "first@test.iam.gserviceaccount.com",
+            "projects/-/serviceAccounts/second@test.iam.gserviceaccount.com",
+        ]);
+        assert_eq!(
+            credentials.delegates,
+            [
+                "projects/-/serviceAccounts/first@test.iam.gserviceaccount.com",
+                "projects/-/serviceAccounts/second@test.iam.gserviceaccount.com"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lifetime() {
+        let client = reqwest::Client::new();
+        let request =
+            |lifetime| generate_access_token(&client, "http://localhost", "", &[], "", lifetime);
+        assert!(matches!(
+            request(43201).await,
+            Err(TokenGenerationError::InvalidImpersonationLifetime(43201))
+        ));
+        assert!(matches!(
+            request(29).await,
+            Err(TokenGenerationError::InvalidImpersonationLifetime(29))
+        ));
+    }
+
+    #[test]
+    fn test_build_from_json_unsupported_source() {
+        let json = r#"{
+            "type": "impersonated_service_account",
+            "service_account_impersonation_url": "https://iamcredentials.googleapis.com/v1/projects/-/serviceAccounts/target@test.iam.gserviceaccount.com:generateAccessToken",
+            "source_credentials": { "type": "authorized_user" }
+        }"#;
+        assert!(matches!(
+            ImpersonatedCredentials::build_from_json(json, &Usage::CloudPlatform),
+            Err(TokenGenerationError::UnsupportedCredentialType(source)) if source == "authorized_user"
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+use crate::error::Result;
+use crate::error::TokenGenerationError;
+use crate::provider::{AccessToken, TokenProvider};
+use chrono::{Duration, Utc};
+use std::sync::{Arc, PoisonError, RwLock};
+use tokio::task::JoinHandle;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+
+/// The token is replaced this long before it expires.
+/// It is shorter than the refresh margin of the token buffer, so the provider returns a new token.
+static REFRESH_MARGIN_SECONDS: i64 = 240;
+/// The shortest pause between two refreshes, also the pause after a failed refresh.
+static MIN_REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// This struct is an [`Interceptor`] for tonic clients that adds the `authorization` metadata
+/// with a token of a [`TokenProvider`], e.g. an [`crate::AuthConfig`].
+///
+/// Interceptors are synchronous, so the token is refreshed by a background task shortly before it expires
+/// and the interceptor only reads the current one.
+/// The quota project of the provider is sent as `x-goog-user-project`.
+/// If the refresh keeps failing until the token expired, the requests fail with [`tonic::Code::Unauthenticated`],
+/// see [`AuthInterceptor::last_refresh_error`] for the cause.
+/// The background task stops once the last clone of the interceptor is dropped.
+#[derive(Clone)]
+pub struct AuthInterceptor {
+    token: Arc<RwLock<AccessToken>>,
+    last_error: Arc<RwLock<Option<String>>>,
+    quota_project: Option<MetadataValue<Ascii>>,
+    _refresh: Arc<RefreshTask>,
+}
+
+impl AuthInterceptor {
+    /// This function requests the first token of the `provider` and starts the background refresh.
+    /// It must be called within a tokio runtime.
+    /// # Errors
+    /// The error of the first token request, see [`crate::Error`].
+    pub async fn new<P: TokenProvider + 'static>(provider: P) -> Result<Self> {
+        Self::from_arc(Arc::new(provider)).await
+    }
+
+    /// This function generates an interceptor that shares the `provider` and its token buffer with other users.
+    /// # Errors
+    /// The error of the first token request, see [`crate::Error`].
+    /// A quota project that is no valid metadata value results in [`crate::Error::InvalidHeaderValue`].
+    pub async fn from_arc<P: TokenProvider + ?Sized + 'static>(provider: Arc<P>) -> Result<Self> {
+        let quota_project = provider
+            .quota_project_id()
+            .map(|quota_project_id| {
+                MetadataValue::try_from(quota_project_id).map_err(|_| {
+                    TokenGenerationError::InvalidHeaderValue(String::from("x-goog-user-project"))
+                })
+            })
+            .transpose()?;
+        let token = Arc::new(RwLock::new(provider.access_token().await?));
+        let last_error = Arc::new(RwLock::new(None));
+        let refresh = tokio::spawn(refresh(
+            provider,
+            Arc::clone(&token),
+            Arc::clone(&last_error),
+        ));
+        Ok(Self {
+            token,
+            last_error,
+            quota_project,
+            _refresh: Arc::new(RefreshTask(refresh)),
+        })
+    }
+
+    /// The error of the last background refresh, `None` once a refresh succeeded again.
+    #[must_use]
+    pub fn last_refresh_error(&self) -> Option<String> {
+        self.last_error
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> std::result::Result<Request<()>, Status> {
+        let token = self
+            .token
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        if token.expires_at() <= Utc::now() {
+            let message = self.last_refresh_error().map_or_else(
+                || String::from("The access token expired and could not be refreshed."),
+                |error| format!("The access token expired and could not be refreshed: {error}"),
+            );
+            return Err(Status::unauthenticated(message));
+        }
+        let mut value = MetadataValue::try_from(format!("Bearer {}", token.as_str()))
+            .map_err(|_| Status::unauthenticated("The access token is no valid metadata value."))?;
+        value.set_sensitive(true);
+        request.metadata_mut().insert("authorization", value);
+        if let Some(quota_project) = &self.quota_project {
+            request
+                .metadata_mut()
+                .insert("x-goog-user-project", quota_project.clone());
+        }
+        Ok(request)
+    }
+}
+
+impl std::fmt::Debug for AuthInterceptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthInterceptor").finish_non_exhaustive()
+    }
+}
+
+/// Aborts the background refresh when it is dropped.
+struct RefreshTask(JoinHandle<()>);
+
+impl Drop for RefreshTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Replaces the token shortly before it expires, a failed refresh is recorded and repeated after a short pause.
+async fn refresh<P: TokenProvider + ?Sized>(
+    provider: Arc<P>,
+    token: Arc<RwLock<AccessToken>>,
+    last_error: Arc<RwLock<Option<String>>>,
+) {
+    loop {
+        let refresh_at = token
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .expires_at()
+            - Duration::seconds(REFRESH_MARGIN_SECONDS);
+        let pause = (refresh_at - Utc::now())
+            .to_std()
+            .unwrap_or_default()
+            .max(MIN_REFRESH_INTERVAL);
+        tokio::time::sleep(pause).await;
+        let error = match provider.access_token().await {
+            Ok(fresh) => {
+                *token.write().unwrap_or_else(PoisonError::into_inner) = fresh;
+                None
+            }
+            Err(error) => Some(error.to_string()),
+        };
+        *last_error.write().unwrap_or_else(PoisonError::into_inner) = error;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use tonic::Code;
+
+    /// Hands out `token-1`, `token-2`, ... the first one is expired.
+    /// The second request fails if `fails_once` is set.
+    #[derive(Default)]
+    struct CountingProvider {
+        requests: AtomicI64,
+        fails_once: bool,
+    }
+
+    #[async_trait]
+    impl TokenProvider for CountingProvider {
+        async fn access_token(&self) -> Result<AccessToken> {
+            let count = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+            if self.fails_once && count == 2 {
+                return Err(TokenGenerationError::Timeout(String::from(
+                    "refresh failed",
+                )));
+            }
+            let expires_in = if count == 1 { 0 } else { 3600 };
+            Ok(AccessToken::expiring_in(
+                format!("token-{count}"),
+                expires_in,
+                Utc::now(),
+            ))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_background_refresh() {
+        let mut interceptor = AuthInterceptor::new(CountingProvider::default())
+            .await
+            .unwrap();
+        let status = interceptor.call(Request::new(())).unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+
+        tokio::time::sleep(MIN_REFRESH_INTERVAL * 2).await;
+        // Clones share the refreshed token.
+        let request = interceptor.clone().call(Request::new(())).unwrap();
+        assert_eq!(
+            request.metadata().get("authorization").unwrap(),
+            "Bearer token-2"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_refresh_error() {
+        let mut interceptor = AuthInterceptor::new(CountingProvider {
+            fails_once: true,
+            ..CountingProvider::default()
+        })
+        .await
+        .unwrap();
+        tokio::time::sleep(MIN_REFRESH_INTERVAL + MIN_REFRESH_INTERVAL / 2).await;
+        assert!(interceptor
+            .last_refresh_error()
+            .unwrap()
+            .contains("refresh failed"));
+        let status = interceptor.call(Request::new(())).unwrap_err();
+        assert!(status.message().contains("refresh failed"));
+
+        // The next refresh succeeds and clears the error.
+        tokio::time::sleep(MIN_REFRESH_INTERVAL).await;
+        assert!(interceptor.last_refresh_error().is_none());
+        assert!(interceptor.call(Request::new(())).is_ok());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::headers::AuthHeaders;
+use crate::provider::TokenProvider;
+use http::{Request, Response, StatusCode};
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The error of an [`AuthService`], either a [`crate::Error`] of the token or an error of the wrapped service.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// This struct is a [`Layer`] that authorizes every request of the wrapped http service
+/// with a token of a [`TokenProvider`], e.g. an [`crate::AuthConfig`].
+///
+/// The token is taken from the buffer of the provider and sent as `Authorization: Bearer` header.
+/// If the upstream answers `401 Unauthorized`, the token is refreshed and the request is sent once more.
+/// To replay it, the request body must implement [`Clone`].
+pub struct AuthLayer<P: ?Sized> {
+    provider: Arc<P>,
+}
+
+impl<P: TokenProvider> AuthLayer<P> {
+    /// This function generates a layer that authorizes the requests with the tokens of the `provider`.
+    pub fn new(provider: P) -> Self {
+        Self {
+            provider: Arc::new(provider),
+        }
+    }
+}
+
+impl<P: TokenProvider + ?Sized> AuthLayer<P> {
+    /// This function generates a layer that shares the `provider` and its token buffer with other users.
+    pub const fn from_arc(provider: Arc<P>) -> Self {
+        Self { provider }
+    }
+}
+
+impl<P: ?Sized> Clone for AuthLayer<P> {
+    fn clone(&self) -> Self {
+        Self {
+            provider: Arc::clone(&self.provider),
+        }
+    }
+}
+
+impl<S, P: ?Sized> Layer<S> for AuthLayer<P> {
+    type Service = AuthService<S, P>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthService {
+            inner,
+            provider: Arc::clone(&self.provider),
+        }
+    }
+}
+
+/// The http service created by an [`AuthLayer`].
+pub struct AuthService<S, P: ?Sized> {
+    inner: S,
+    provider: Arc<P>,
+}
+
+impl<S: Clone, P: ?Sized> Clone for AuthService<S, P> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            provider: Arc::clone(&self.provider),
+        }
+    }
+}
+
+impl<S, P, ReqBody, ResBody> Service<Request<ReqBody>> for AuthService<S, P>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    P: TokenProvider + ?Sized + 'static,
+    ReqBody: Clone + Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = BoxError;
+    type Future =
+        Pin<Box<dyn Future<Output = std::result::Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), BoxError>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        // The ready service is used for the first attempt, the clone waits for readiness on a retry.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let provider = Arc::clone(&self.provider);
+        Box::pin(async move {
+            AuthHeaders::build(&provider.access_token().await?, provider.quota_project_id())?
+                .apply_to_request(&mut request);
+            let mut retry = clone_request(&request);
+            let response = inner.call(request).await.map_err(Into::into)?;
+            if response.status() != StatusCode::UNAUTHORIZED {
+                return Ok(response);
+            }
+            AuthHeaders::build(
+                &provider.refresh_access_token().await?,
+                provider.quota_project_id(),
+            )?
+            .apply_to_request(&mut retry);
+            poll_fn(|cx| inner.poll_ready(cx))
+                .await
+                .map_err(Into::into)?;
+            inner.call(retry).await.map_err(Into::into)
+        })
+    }
+}
+
+fn clone_request<B: Clone>(request: &Request<B>) -> Request<B> {
+    let mut clone = Request::new(request.body().clone());
+    *clone.method_mut() = request.method().clone();
+    *clone.uri_mut() = request.uri().clone();
+    *clone.version_mut() = request.version();
+    *clone.headers_mut() = request.headers().clone();
+    *clone.extensions_mut() = request.extensions().clone();
+    clone
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::error::Result;
+    use crate::provider::AccessToken;
+    use async_trait::async_trait;
+    use http::header::AUTHORIZATION;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// Hands out `token-1`, `token-2`, ... and counts the refreshes.
+    #[derive(Default)]
+    struct CountingProvider {
+        refreshes: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TokenProvider for CountingProvider {
+        async fn access_token(&self) -> Result<AccessToken> {
+            let token = format!("token-{}", self.refreshes.load(Ordering::Relaxed) + 1);
+            Ok(AccessToken::expiring_in(token, 3600, chrono::Utc::now()))
+        }
+
+        async fn refresh_access_token(&self) -> Result<AccessToken> {
+            self.refreshes.fetch_add(1, Ordering::Relaxed);
+            self.access_token().await
+        }
+    }
+
+    /// Answers `401` unless the request carries `Bearer token-2`, records the authorization headers.
+    #[derive(Clone, Default)]
+    struct Upstream {
+        authorizations: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Service<Request<String>> for Upstream {
+        type Response = Response<String>;
+        type Error = BoxError;
+        type Future = std::future::Ready<std::result::Result<Response<String>, BoxError>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<std::result::Result<(), BoxError>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<String>) -> Self::Future {
+            let authorization = request.headers()[AUTHORIZATION]
+                .to_str()
+                .unwrap()
+                .to_string();
+            let status = if authorization == "Bearer token-2" {
+                StatusCode::OK
+            } else {
+                StatusCode::UNAUTHORIZED
+            };
+            self.authorizations.lock().unwrap().push(authorization);
+            let mut response = Response::new(request.into_body());
+            *response.status_mut() = status;
+            std::future::ready(Ok(response))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_unauthorized() {
+        let upstream = Upstream::default();
+        let provider = Arc::new(CountingProvider::default());
+        let mut service = AuthLayer::from_arc(Arc::clone(&provider)).layer(upstream.clone());
+
+        poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+        let response = service
+            .call(Request::new(String::from("body")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), "body");
+        assert_eq!(
+            *upstream.authorizations.lock().unwrap(),
+            ["Bearer token-1", "Bearer token-2"]
+        );
+
+        // A second rejection is passed on instead of refreshing again.
+        provider.refreshes.store(2, Ordering::Relaxed);
+        poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+        let response = service.call(Request::new(String::new())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(provider.refreshes.load(Ordering::Relaxed), 3);
+    }
+}
@@ -20,7 +20,8 @@
 //! 2. Generate a token with [`AuthConfig::generate_auth_token()`]
 //!
 //! # Example
-//! ```
+#![cfg_attr(feature = "http", doc = "```")]
+#![cfg_attr(not(feature = "http"), doc = "```ignore")]
 //! use google_jwt_auth::AuthConfig;
 //! use google_jwt_auth::usage::Usage;
 //!
@@ -36,34 +37,139 @@
 //! ```
 //! After awaiting the `token_response` the result can be obtained.
 
-use crate::error::{Result, TokenGenerationError};
-use crate::json_structs::{Claims, GoogleResponse, ServiceAccountInfoJson};
-
+#[cfg(feature = "http")]
+use crate::assertion::{token_request_body, CONTENT_TYPE};
+#[cfg(feature = "http")]
+use crate::cache::TokenCache;
+#[cfg(feature = "http")]
+use crate::clock::{Clock, SystemClock};
+use crate::error::TokenGenerationError;
+#[cfg(feature = "http")]
+use crate::error::{ErrorKind, Result};
+#[cfg(feature = "http")]
+use crate::http_client::HttpClient;
+#[cfg(feature = "http")]
+use crate::json_structs::{
+    Claims, GoogleResponse, IdTokenResponse, ServiceAccountInfoJson, ValidResponse,
+};
+#[cfg(feature = "http")]
+use crate::provider::{resolve_quota_project_id, AccessToken, TokenProvider};
+#[cfg(feature = "http")]
+use crate::retry::RetryPolicy;
+#[cfg(feature = "http")]
+use crate::secret::SecretString;
+#[cfg(feature = "http")]
+use crate::signer::{AssertionSigner, IamSigner, Signer};
+#[cfg(feature = "http")]
+use crate::universe::{check_endpoint, service_endpoint, DEFAULT_UNIVERSE_DOMAIN};
+#[cfg(feature = "http")]
 use crate::usage::Usage;
-use jsonwebtoken::{Algorithm, EncodingKey, Header};
+#[cfg(feature = "http")]
+use async_trait::async_trait;
+#[cfg(feature = "http")]
+use chrono::{DateTime, Utc};
+#[cfg(feature = "http")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "http")]
+use std::collections::HashMap;
+#[cfg(feature = "http")]
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+#[cfg(feature = "http")]
+use std::sync::Arc;
+#[cfg(feature = "http")]
+use std::time::Duration;
+// Without the http feature, the async runtime of the tests is unused.
+#[cfg(all(test, not(feature = "http")))]
+use tokio as _;
+// The argument parser and the http server are only used by the binaries.
+#[cfg(feature = "metadata-server")]
+use axum as _;
+#[cfg(feature = "cli")]
+use clap as _;
 
+/// This module contains the signing of jwt assertions and the parsing of token responses without any transport.
+pub mod assertion;
+/// This module contains the synchronous api that needs no async runtime.
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "http")]
+pub(crate) mod cache;
+/// This module contains the clock used for the jwt claims and the token expiry.
+pub mod clock;
+/// This module contains downscoped tokens restricted by credential access boundaries.
+#[cfg(feature = "http")]
+pub mod downscoped;
 /// This module contains all error types and meanings.
 pub mod error;
+/// This module contains the workload identity federation credentials for workloads running on AWS.
+#[cfg(feature = "http")]
+pub mod external_account;
+/// This module contains the headers that authorize google api requests.
+#[cfg(feature = "http")]
+pub mod headers;
+#[cfg(feature = "http")]
+mod http_client;
+/// This module contains the service account impersonation via the IAM credentials api.
+#[cfg(feature = "http")]
+pub mod impersonated;
+/// This module contains the tonic interceptor that authorizes grpc requests.
+#[cfg(feature = "tonic")]
+pub mod interceptor;
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
 pub(crate) mod json_structs;
+/// This module contains the tower middleware that authorizes http requests.
+#[cfg(feature = "tower")]
+pub mod layer;
+/// This module contains the reqwest middleware that authorizes http requests.
+#[cfg(feature = "reqwest-middleware")]
+pub mod middleware;
+/// This module contains the common interface of all credential types.
+pub mod provider;
+/// This module contains the retry policy of failed token requests.
+#[cfg(feature = "http")]
+pub mod retry;
+/// This module contains the wrapper that keeps private keys and tokens out of logs.
+pub mod secret;
+/// This module contains the signers of the jwt assertions.
+pub mod signer;
+#[cfg(feature = "http")]
+pub(crate) mod sigv4;
+#[cfg(all(test, feature = "http"))]
+mod test_server;
+pub(crate) mod universe;
 /// This module contains all types of usages and their description.
 pub mod usage;
 
 /// This typing is used to have easy access to the library errors.
 pub type Error = TokenGenerationError;
 
-static GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
-static CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+#[cfg(feature = "http")]
+static DEFAULT_LIFETIME: i64 = 3600;
+#[cfg(feature = "http")]
+static DEFAULT_IAT_BACKDATE: Duration = Duration::from_secs(10);
 
 /// This struct contains all necessary information to request an authentication token from Google.
 /// This structure is intended to be reused by the client for several token generation requests.
+#[cfg(feature = "http")]
 pub struct AuthConfig {
-    header: Header,
     iss: String,
     scope: String,
     aud: String,
-    private_key: String,
+    signers: Vec<AssertionSigner>,
+    active_signer: AtomicUsize,
+    universe_domain: String,
+    retry_policy: RetryPolicy,
+    http: HttpClient,
+    deadline: Option<Duration>,
+    cache: Arc<TokenCache>,
+    scoped_caches: std::sync::Mutex<HashMap<String, Arc<TokenCache>>>,
+    clock: Arc<dyn Clock>,
+    iat_backdate: Duration,
+    clock_skew: AtomicI64,
+    quota_project_id: Option<String>,
 }
 
+#[cfg(feature = "http")]
 impl AuthConfig {
     /// This function generates an auth configuration with the provided information. A config is used to request `auth_tokens`.
     /// This function generates only tokens with the RS256 encryption like the Google jwt authentication service does.
@@ -84,17 +190,288 @@ impl AuthConfig {
     /// Inputs out of this ranged will not be accepted.
     /// # Errors
     /// See [`Error`] for a more detailed answer.
+    /// The private key is parsed right away, an unusable key results in [`Error::InvalidPrivateKey`].
     /// # Returns
     /// The above-mentioned jwt as String.
     pub fn build(service_account_json_str: &str, usage: &Usage) -> Result<Self> {
-        let account_info: ServiceAccountInfoJson = serde_json::from_str(service_account_json_str)?;
-        Ok(Self {
-            header: Header::new(Algorithm::RS256),
-            iss: account_info.client_email,
+        Self::build_with_keys(&[service_account_json_str], usage)
+    }
+
+    /// This function generates an auth configuration that holds several keys of one service account.
+    /// The first key is used until the authentication service rejects it with `invalid_grant`,
+    /// e.g. because it got deleted in the Google console. The next key is tried right away
+    /// and stays in use for the following requests, so rolling a key causes no downtime.
+    /// # Params
+    /// **`service_account_json_strs`: [String]**<br>
+    /// The key files of the service account, in the order they should be used.
+    /// All of them must belong to the same service account and token uri.
+    ///
+    /// **`usage`: String**<br>
+    /// Each google api request requires individual permissions to be executed.
+    /// See here for more information: [Google Scopes](https://developers.google.com/identity/protocols/oauth2/scopes?hl=en).
+    /// # Errors
+    /// See [`Error`] for a more detailed answer.
+    pub fn build_with_keys<S: AsRef<str>>(
+        service_account_json_strs: &[S],
+        usage: &Usage,
+    ) -> Result<Self> {
+        let mut accounts = service_account_json_strs
+            .iter()
+            .map(|json| ServiceAccountInfoJson::parse(json.as_ref()))
+            .collect::<std::result::Result<Vec<_>, _>>()?
+            .into_iter();
+        let first = accounts.next().ok_or_else(|| {
+            Error::InconsistentKeys(String::from("At least one key must be provided."))
+        })?;
+        let universe_domain = first
+            .universe_domain
+            .clone()
+            .unwrap_or_else(|| DEFAULT_UNIVERSE_DOMAIN.to_string());
+        check_endpoint(&first.token_uri, &universe_domain)?;
+        let mut signers = vec![AssertionSigner::local(
+            first.private_key.expose_secret(),
+            &first.private_key_id,
+        )?];
+        for account in accounts {
+            if account.client_email != first.client_email
+                || account.token_uri != first.token_uri
+                || account.universe_domain != first.universe_domain
+            {
+                return Err(Error::InconsistentKeys(format!(
+                    "The key '{}' belongs to '{}', expected '{}'.",
+                    account.private_key_id, account.client_email, first.client_email
+                )));
+            }
+            signers.push(AssertionSigner::local(
+                account.private_key.expose_secret(),
+                &account.private_key_id,
+            )?);
+        }
+        let mut config = Self::new(
+            first.client_email,
+            usage,
+            first.token_uri,
+            signers,
+            universe_domain,
+        );
+        config.quota_project_id = resolve_quota_project_id(first.quota_project_id);
+        Ok(config)
+    }
+
+    /// This function generates an auth configuration from a legacy P12 key file.
+    /// Google protects all P12 keys with the password `notasecret`, the RSA key is extracted with it.
+    /// # Params
+    /// **`p12`: [u8]**<br>
+    /// The content of the `.p12` file.
+    ///
+    /// **`client_email`: String**<br>
+    /// The email of the service account the key belongs to. It is not part of the P12 file.
+    ///
+    /// **`token_uri`: String**<br>
+    /// The token uri of the service account, usually `https://oauth2.googleapis.com/token`.
+    ///
+    /// **`usage`: String**<br>
+    /// Each google api request requires individual permissions to be executed.
+    /// See here for more information: [Google Scopes](https://developers.google.com/identity/protocols/oauth2/scopes?hl=en).
+    /// # Errors
+    /// An unreadable file or key results in [`Error::InvalidPrivateKey`].
+    /// P12 keys only exist in the `googleapis.com` universe, another `token_uri` results in [`Error::UniverseDomainMismatch`].
+    pub fn build_from_p12(
+        p12: &[u8],
+        client_email: &str,
+        token_uri: &str,
+        usage: &Usage,
+    ) -> Result<Self> {
+        check_endpoint(token_uri, DEFAULT_UNIVERSE_DOMAIN)?;
+        Ok(Self::new(
+            client_email.to_string(),
+            usage,
+            token_uri.to_string(),
+            vec![AssertionSigner::local_p12(p12)?],
+            DEFAULT_UNIVERSE_DOMAIN.to_string(),
+        ))
+    }
+
+    /// This function generates an auth configuration whose jwt assertions are signed remotely
+    /// by the IAM credentials api instead of a local private key.
+    /// The tokens are issued for the service account of the `signer`, in the universe domain of its source credential.
+    /// # Params
+    /// **`signer`: `IamSigner`**<br>
+    /// The remote signer of the service account, see [`IamSigner::build`].
+    ///
+    /// **`usage`: String**<br>
+    /// Each google api request requires individual permissions to be executed.
+    /// See here for more information: [Google Scopes](https://developers.google.com/identity/protocols/oauth2/scopes?hl=en).
+    #[must_use]
+    pub fn build_with_iam_signer(signer: IamSigner, usage: &Usage) -> Self {
+        let universe_domain = signer.universe_domain().to_string();
+        Self::new(
+            signer.service_account_email().to_string(),
+            usage,
+            default_token_uri(&universe_domain),
+            vec![AssertionSigner::Iam(signer)],
+            universe_domain,
+        )
+    }
+
+    /// This function generates an auth configuration whose jwt assertions are signed by a custom backend,
+    /// e.g. a Cloud KMS asymmetric key or an HSM, instead of a PEM private key.
+    /// The tokens are requested in the `googleapis.com` universe, see [`AuthConfig::with_universe_domain`] for other universes.
+    /// # Params
+    /// **`client_email`: String**<br>
+    /// The email of the service account the signing key belongs to.
+    ///
+    /// **`signer`: `Signer`**<br>
+    /// The signing backend. Its public key must be uploaded to the service account.
+    ///
+    /// **`usage`: String**<br>
+    /// Each google api request requires individual permissions to be executed.
+    /// See here for more information: [Google Scopes](https://developers.google.com/identity/protocols/oauth2/scopes?hl=en).
+    pub fn build_with_signer(
+        client_email: &str,
+        signer: impl Signer + 'static,
+        usage: &Usage,
+    ) -> Self {
+        Self::new(
+            client_email.to_string(),
+            usage,
+            default_token_uri(DEFAULT_UNIVERSE_DOMAIN),
+            vec![AssertionSigner::Custom(Box::new(signer))],
+            DEFAULT_UNIVERSE_DOMAIN.to_string(),
+        )
+    }
+
+    fn new(
+        iss: String,
+        usage: &Usage,
+        aud: String,
+        signers: Vec<AssertionSigner>,
+        universe_domain: String,
+    ) -> Self {
+        Self {
+            iss,
             scope: usage.as_string(),
-            aud: account_info.token_uri,
-            private_key: account_info.private_key,
-        })
+            aud,
+            signers,
+            active_signer: AtomicUsize::new(0),
+            universe_domain,
+            retry_policy: RetryPolicy::default(),
+            http: HttpClient::default(),
+            deadline: None,
+            cache: Arc::new(TokenCache::new()),
+            scoped_caches: std::sync::Mutex::default(),
+            clock: Arc::new(SystemClock),
+            iat_backdate: DEFAULT_IAT_BACKDATE,
+            clock_skew: AtomicI64::new(0),
+            quota_project_id: resolve_quota_project_id(None),
+        }
+    }
+
+    /// Moves a configuration with a custom signer into another universe domain, e.g. a sovereign cloud.
+    /// The token uri becomes `https://oauth2.{universe_domain}/token`.
+    /// Key files and IAM signers already determine their universe domain.
+    /// # Errors
+    /// Changing the universe domain of a key file or IAM signer configuration results in [`Error::UniverseDomainMismatch`].
+    pub fn with_universe_domain(mut self, universe_domain: &str) -> Result<Self> {
+        if self.universe_domain == universe_domain {
+            return Ok(self);
+        }
+        if !self
+            .signers
+            .iter()
+            .all(|signer| matches!(signer, AssertionSigner::Custom(_)))
+        {
+            return Err(Error::UniverseDomainMismatch(
+                self.aud,
+                universe_domain.to_string(),
+            ));
+        }
+        self.aud = default_token_uri(universe_domain);
+        self.universe_domain = universe_domain.to_string();
+        Ok(self)
+    }
+
+    /// Replaces the retry policy of failed token requests, by default [`RetryPolicy::default`].
+    /// Use [`RetryPolicy::none`] to fail on the first error.
+    #[must_use]
+    pub const fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Limits the time to establish a connection to the authentication service, by default 10 seconds.
+    /// `None` waits as long as the operating system does.
+    #[must_use]
+    pub fn with_connect_timeout(mut self, connect_timeout: Option<Duration>) -> Self {
+        self.http = self.http.with_connect_timeout(connect_timeout);
+        self
+    }
+
+    /// Limits the time of a single token request from connecting until the response body is read,
+    /// by default 30 seconds. `None` disables the limit.
+    #[must_use]
+    pub fn with_request_timeout(mut self, request_timeout: Option<Duration>) -> Self {
+        self.http = self.http.with_request_timeout(request_timeout);
+        self
+    }
+
+    /// Limits the total time of a token generation including all retries and key rotations.
+    /// By default only the retry policy limits the total time.
+    /// See [`AuthConfig::generate_auth_token_with_deadline`] to override it for a single call.
+    #[must_use]
+    pub const fn with_deadline(mut self, deadline: Option<Duration>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Replaces the clock of the jwt claims and the token expiry, by default [`SystemClock`].
+    #[must_use]
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets how far the issue time (`iat`) of the assertions lies in the past, by default 10 seconds.
+    /// The authentication service rejects assertions issued in the future,
+    /// so a host clock running a few seconds fast still gets tokens.
+    #[must_use]
+    pub const fn with_iat_backdate(mut self, iat_backdate: Duration) -> Self {
+        self.iat_backdate = iat_backdate;
+        self
+    }
+
+    /// Replaces the `quota_project_id` of the key file and the `GOOGLE_CLOUD_QUOTA_PROJECT` environment variable.
+    /// The quota project is billed for the api requests, `None` bills the project of the service account.
+    #[must_use]
+    pub fn with_quota_project_id(mut self, quota_project_id: Option<&str>) -> Self {
+        self.quota_project_id = quota_project_id.map(str::to_string);
+        self
+    }
+
+    /// The offset in seconds between the clock of the authentication service and the own clock.
+    /// It is learned from the `Date` header after an assertion got rejected for its timeframe
+    /// and added to the issue time of all following assertions.
+    #[must_use]
+    pub fn clock_skew(&self) -> i64 {
+        self.clock_skew.load(Ordering::Relaxed)
+    }
+
+    /// The universe domain the tokens are valid in, `googleapis.com` for the public Google cloud.
+    #[must_use]
+    pub fn universe_domain(&self) -> &str {
+        &self.universe_domain
+    }
+
+    /// The email of the service account the tokens are issued for.
+    #[must_use]
+    pub fn client_email(&self) -> &str {
+        &self.iss
+    }
+
+    /// The project that is billed for the api requests, see [`AuthConfig::with_quota_project_id`].
+    #[must_use]
+    pub fn quota_project_id(&self) -> Option<&str> {
+        self.quota_project_id.as_deref()
     }
 
     /// With the provided jwt token, an authentication token (short: `auth_token`) will be requested from Google.
@@ -103,51 +480,423 @@ impl AuthConfig {
     /// See [`Error`] for a more detailed answer.
     /// # Returns
     /// The above-mentioned `auth_token` as String.
-    pub async fn generate_auth_token(&self, lifetime: i64) -> Result<String> {
-        if !(30..=3600).contains(&lifetime) {
-            return Err(Error::InvalidLifetime(lifetime));
+    pub async fn generate_auth_token(&self, lifetime: i64) -> Result<SecretString> {
+        Ok(self
+            .request_access_token(&self.scope, lifetime, self.deadline)
+            .await?
+            .into_secret())
+    }
+
+    /// Works like [`AuthConfig::generate_auth_token`], but fails with [`Error::Timeout`]
+    /// if no token is available within the `deadline` instead of the configured one.
+    /// # Errors
+    /// See [`Error`] for a more detailed answer.
+    pub async fn generate_auth_token_with_deadline(
+        &self,
+        lifetime: i64,
+        deadline: Duration,
+    ) -> Result<SecretString> {
+        Ok(self
+            .request_access_token(&self.scope, lifetime, Some(deadline))
+            .await?
+            .into_secret())
+    }
+
+    /// An `OpenID Connect` id token of the service account will be requested from Google,
+    /// e.g. to call a Cloud Run service or an IAP protected application.
+    /// # Params
+    /// **`audience`: String**<br>
+    /// The audience (`aud` claim) of the id token, usually the url of the receiving service.
+    /// # Errors
+    /// See [`Error`] for a more detailed answer.
+    /// # Returns
+    /// The above-mentioned id token as String.
+    pub async fn generate_id_token(&self, audience: &str) -> Result<SecretString> {
+        self.request_token::<IdTokenResponse>(&|| self.id_token_claims(audience), self.deadline)
+            .await
+    }
+
+    /// The id of the key that signs the next assertion, if it is known in advance.
+    #[must_use]
+    pub fn active_key_id(&self) -> Option<String> {
+        self.signers[self.active_signer.load(Ordering::Relaxed)].key_id()
+    }
+
+    /// Works like [`TokenProvider::access_token`], but with the scope of `usage` instead of the configured one,
+    /// e.g. for a single api that needs another permission. The tokens of each scope are buffered separately.
+    /// Scopes that only differ in order share a buffer, and at most 16 scopes besides the configured one are buffered at once.
+    /// # Errors
+    /// See [`Error`] for a more detailed answer.
+    pub async fn access_token_for(&self, usage: &Usage) -> Result<AccessToken> {
+        let scope = normalize_scope(&usage.as_string());
+        self.scoped_cache(&scope)
+            .get_or_refresh(self.clock.now(), || {
+                self.request_access_token(&scope, DEFAULT_LIFETIME, self.deadline)
+            })
+            .await
+    }
+
+    /// Works like [`TokenProvider::refresh_access_token`] for the scope of `usage`, see [`AuthConfig::access_token_for`].
+    /// # Errors
+    /// See [`Error`] for a more detailed answer.
+    pub async fn refresh_access_token_for(&self, usage: &Usage) -> Result<AccessToken> {
+        let scope = normalize_scope(&usage.as_string());
+        self.scoped_cache(&scope)
+            .force_refresh(|| self.request_access_token(&scope, DEFAULT_LIFETIME, self.deadline))
+            .await
+    }
+
+    /// The token buffer of a normalized scope, the configured scope shares the buffer of [`TokenProvider::access_token`].
+    fn scoped_cache(&self, scope: &str) -> Arc<TokenCache> {
+        if scope == normalize_scope(&self.scope) {
+            return Arc::clone(&self.cache);
         }
+        scoped_entry(&self.scoped_caches, scope, TokenCache::new)
+    }
 
-        // TODO add token buffer with lifetime check to minimize auth_token requests
-        // <--
+    async fn request_access_token(
+        &self,
+        scope: &str,
+        lifetime: i64,
+        deadline: Option<Duration>,
+    ) -> Result<AccessToken> {
+        check_lifetime(lifetime)?;
+        self.request_token::<ValidResponse>(&|| self.claims(scope, lifetime), deadline)
+            .await
+    }
+
+    /// Requests a token of the response type `R` with assertions of the `claims`,
+    /// within the `deadline` if there is one.
+    async fn request_token<R: AssertionResponse>(
+        &self,
+        claims: &(dyn Fn() -> Claims + Sync),
+        deadline: Option<Duration>,
+    ) -> Result<R::Token> {
+        let Some(deadline) = deadline else {
+            return self.request_with_rotation::<R>(claims).await;
+        };
+        tokio::time::timeout(deadline, self.request_with_rotation::<R>(claims))
+            .await
+            .map_err(|_| Error::Timeout(format!("No token within the deadline of {deadline:?}.")))?
+    }
+
+    /// Requests a token with the active key and falls through to the next keys on `invalid_grant`.
+    async fn request_with_rotation<R: AssertionResponse>(
+        &self,
+        claims: &(dyn Fn() -> Claims + Sync),
+    ) -> Result<R::Token> {
+        let mut rotation = self.key_rotation();
+        while let Some(signer) = rotation.next_signer() {
+            let result = self
+                .retry_policy
+                .run(|| self.request_signed_token::<R>(signer, claims))
+                .await;
+            if let Some(result) = rotation.complete(result) {
+                return result;
+            }
+        }
+        rotation.finish()
+    }
+
+    /// The keys of a token request, starting with the active one.
+    fn key_rotation(&self) -> KeyRotation<'_> {
+        KeyRotation {
+            config: self,
+            active: self.active_signer.load(Ordering::Relaxed),
+            offset: 0,
+            last_error: None,
+        }
+    }
+
+    /// Sends an assertion and resends it once if it got rejected for its timeframe
+    /// and the `Date` header of the response reveals a different clock skew.
+    async fn request_signed_token<R: AssertionResponse>(
+        &self,
+        signer: &AssertionSigner,
+        claims: &(dyn Fn() -> Claims + Sync),
+    ) -> Result<R::Token> {
+        let mut server_date = None;
+        let result = self
+            .send_assertion::<R>(signer, &claims(), &mut server_date)
+            .await;
+        if self.resends_assertion(&result, server_date) {
+            return self.send_assertion::<R>(signer, &claims(), &mut None).await;
+        }
+        result
+    }
+
+    /// Whether a failed assertion is sent once more: it got rejected for its timeframe
+    /// and the `Date` header of the response reveals a different clock skew, which is learned.
+    fn resends_assertion<T>(&self, result: &Result<T>, server_date: Option<DateTime<Utc>>) -> bool {
+        matches!(result, Err(error) if error.kind() == ErrorKind::ClockSkew)
+            && server_date.is_some_and(|server_date| self.learn_clock_skew(server_date))
+    }
 
-        let claims = Claims::new(
+    /// Stores the offset to the clock of the authentication service and returns whether it changed.
+    fn learn_clock_skew(&self, server_date: DateTime<Utc>) -> bool {
+        let clock_skew = (server_date - self.clock.now()).num_seconds();
+        self.clock_skew.swap(clock_skew, Ordering::Relaxed) != clock_skew
+    }
+
+    /// The claims of the next assertion, issued at the corrected time minus the backdate.
+    fn claims(&self, scope: &str, lifetime: i64) -> Claims {
+        let issued_at = self.clock.now() + chrono::Duration::seconds(self.clock_skew())
+            - chrono::Duration::from_std(self.iat_backdate).unwrap_or_default();
+        Claims::new(
             self.iss.clone(),
-            self.scope.clone(),
+            scope.to_string(),
             self.aud.clone(),
+            issued_at,
             lifetime,
-        );
-        let assertion = self.sign(&claims)?;
+        )
+    }
+
+    /// The claims of an assertion that requests an id token for the `audience`.
+    fn id_token_claims(&self, audience: &str) -> Claims {
+        let mut claims = self.claims("", DEFAULT_LIFETIME);
+        claims.target_audience = Some(audience.to_string());
+        claims
+    }
+
+    /// The token of a successful response, it expires relative to the configured clock.
+    fn token_from_response(&self, resp: ValidResponse) -> AccessToken {
+        AccessToken::expiring_in(resp.access_token, resp.expires_in, self.clock.now())
+    }
+
+    async fn send_assertion<R: AssertionResponse>(
+        &self,
+        signer: &AssertionSigner,
+        claims: &Claims,
+        server_date: &mut Option<DateTime<Utc>>,
+    ) -> Result<R::Token> {
+        let assertion = signer.sign(claims).await?;
 
-        let params = format!("grant_type={GRANT_TYPE}&assertion={assertion}");
-        let resp = reqwest::Client::new()
+        let params = token_request_body(&assertion);
+        let resp = self
+            .http
+            .get()?
             .post(&self.aud)
             .header(reqwest::header::CONTENT_TYPE, CONTENT_TYPE)
             .body(params)
             .send()
-            .await?
-            .json::<GoogleResponse>()
-            .await?;
-        match resp {
-            GoogleResponse::ValidResponse { access_token, .. } => Ok(access_token),
-            GoogleResponse::ErrorResponse {
-                error,
-                error_description,
-                ..
-            } => Err(Error::AuthenticationError(error, error_description)),
+            .await
+            .map_err(map_timeout)?;
+        *server_date = parse_date_header(resp.headers());
+        let resp = GoogleResponse::<R>::read(resp).await.map_err(map_timeout)?;
+        Ok(resp.into_token(self))
+    }
+}
+
+/// A successful response of the authentication service to an assertion.
+#[cfg(feature = "http")]
+trait AssertionResponse: DeserializeOwned {
+    /// The token the response carries.
+    type Token: Send;
+
+    fn into_token(self, config: &AuthConfig) -> Self::Token;
+}
+
+#[cfg(feature = "http")]
+impl AssertionResponse for ValidResponse {
+    type Token = AccessToken;
+
+    fn into_token(self, config: &AuthConfig) -> AccessToken {
+        config.token_from_response(self)
+    }
+}
+
+#[cfg(feature = "http")]
+impl AssertionResponse for IdTokenResponse {
+    type Token = SecretString;
+
+    fn into_token(self, _config: &AuthConfig) -> SecretString {
+        self.id_token
+    }
+}
+
+/// Walks the keys of a token request, shared by the async and the blocking api.
+/// The active key is only updated after a successful request, so a cancelled walk changes nothing.
+#[cfg(feature = "http")]
+struct KeyRotation<'a> {
+    config: &'a AuthConfig,
+    active: usize,
+    offset: usize,
+    last_error: Option<Error>,
+}
+
+#[cfg(feature = "http")]
+impl<'a> KeyRotation<'a> {
+    /// The next key to try, `None` once all keys were tried.
+    fn next_signer(&mut self) -> Option<&'a AssertionSigner> {
+        let signers = &self.config.signers;
+        if self.offset >= signers.len() {
+            return None;
+        }
+        self.offset += 1;
+        Some(&signers[self.index()])
+    }
+
+    /// The final result of the request with the current key, `None` if the next key is worth a try.
+    fn complete<T>(&mut self, result: Result<T>) -> Option<Result<T>> {
+        match result {
+            Err(error) if tries_next_key(&error) => {
+                self.last_error = Some(error);
+                None
+            }
+            Ok(token) => {
+                self.config
+                    .active_signer
+                    .store(self.index(), Ordering::Relaxed);
+                Some(Ok(token))
+            }
+            Err(error) => Some(Err(error)),
         }
     }
 
-    fn sign(&self, claims: &Claims) -> Result<String> {
-        let key = EncodingKey::from_rsa_pem(self.private_key.as_bytes())?;
-        Ok(jsonwebtoken::encode::<Claims>(&self.header, claims, &key)?)
+    /// The error of the last key after all keys got rejected.
+    fn finish<T>(self) -> Result<T> {
+        Err(self
+            .last_error
+            .unwrap_or_else(|| Error::InconsistentKeys(String::from("No key available."))))
+    }
+
+    const fn index(&self) -> usize {
+        (self.active + self.offset - 1) % self.config.signers.len()
     }
 }
 
-#[cfg(test)]
+/// The `Debug` output never contains the private keys.
+#[cfg(feature = "http")]
+impl std::fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("iss", &self.iss)
+            .field("scope", &self.scope)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "http")]
+#[async_trait]
+impl TokenProvider for AuthConfig {
+    /// The token is buffered and refreshed shortly before it expires.
+    async fn access_token(&self) -> Result<AccessToken> {
+        self.cache
+            .get_or_refresh(self.clock.now(), || {
+                self.request_access_token(&self.scope, DEFAULT_LIFETIME, self.deadline)
+            })
+            .await
+    }
+
+    async fn refresh_access_token(&self) -> Result<AccessToken> {
+        self.cache
+            .force_refresh(|| {
+                self.request_access_token(&self.scope, DEFAULT_LIFETIME, self.deadline)
+            })
+            .await
+    }
+
+    fn universe_domain(&self) -> &str {
+        &self.universe_domain
+    }
+
+    fn quota_project_id(&self) -> Option<&str> {
+        self.quota_project_id.as_deref()
+    }
+}
+
+/// At most this many scopes besides the configured one get their own token buffer,
+/// e.g. for the `?scopes=` of the metadata server clients.
+#[cfg(feature = "http")]
+const MAX_SCOPED_CACHES: usize = 16;
+
+/// The scopes of a space separated list sorted and without duplicates,
+/// so lists that only differ in order share a token buffer.
+#[cfg(feature = "http")]
+fn normalize_scope(scope: &str) -> String {
+    let mut scopes: Vec<&str> = scope.split_whitespace().collect();
+    scopes.sort_unstable();
+    scopes.dedup();
+    scopes.join(" ")
+}
+
+/// The token buffer of a scope in `caches`, shared by the async and the blocking api.
+/// A new scope drops an arbitrary buffer once [`MAX_SCOPED_CACHES`] scopes are buffered.
+#[cfg(feature = "http")]
+fn scoped_entry<C>(
+    caches: &std::sync::Mutex<HashMap<String, Arc<C>>>,
+    scope: &str,
+    new: fn() -> C,
+) -> Arc<C> {
+    let mut caches = caches
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    if let Some(cache) = caches.get(scope) {
+        return Arc::clone(cache);
+    }
+    if caches.len() >= MAX_SCOPED_CACHES {
+        if let Some(evicted) = caches.keys().next().cloned() {
+            caches.remove(&evicted);
+        }
+    }
+    let cache = Arc::new(new());
+    caches.insert(scope.to_string(), Arc::clone(&cache));
+    cache
+}
+
+/// Access tokens live between 30 seconds and one hour.
+#[cfg(feature = "http")]
+fn check_lifetime(lifetime: i64) -> Result<()> {
+    if (30..=3600).contains(&lifetime) {
+        Ok(())
+    } else {
+        Err(Error::InvalidLifetime(lifetime))
+    }
+}
+
+/// Whether a rejection of the assertion is worth a try with the next key.
+#[cfg(feature = "http")]
+fn tries_next_key(error: &Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::DeletedKey | ErrorKind::InvalidGrant
+    )
+}
+
+/// The `Date` header of a response of the authentication service.
+#[cfg(feature = "http")]
+fn parse_date_header(headers: &reqwest::header::HeaderMap) -> Option<DateTime<Utc>> {
+    headers
+        .get(reqwest::header::DATE)
+        .and_then(|date| date.to_str().ok())
+        .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// Turns the timeouts of the http client into [`Error::Timeout`].
+#[cfg(feature = "http")]
+fn map_timeout(error: impl Into<Error>) -> Error {
+    match error.into() {
+        Error::ReqwestError(error) if error.is_timeout() => Error::Timeout(String::from(
+            "No response within the connect or request timeout.",
+        )),
+        error => error,
+    }
+}
+
+/// The token uri of the authentication service in a universe.
+#[cfg(feature = "http")]
+fn default_token_uri(universe_domain: &str) -> String {
+    format!("{}/token", service_endpoint("oauth2", universe_domain))
+}
+
+#[cfg(all(test, feature = "http"))]
 mod tests {
     #![allow(clippy::unwrap_used)]
     use super::*;
+    use crate::error::PrivateKeyError;
+    use crate::test_server::{TestResponse, TestServer};
+    use base64::prelude::{Engine, BASE64_URL_SAFE_NO_PAD};
     use std::fs;
 
     #[tokio::test]
@@ -197,6 +946,458 @@ mod tests {
         assert!(valid_config.generate_auth_token(-10).await.is_err());
     }
 
+    #[tokio::test]
+    async fn test_retry() {
+        let mut unavailable = TestResponse::json(503, "{}");
+        unavailable.headers.push(("retry-after", String::from("0")));
+        let server = TestServer::start(vec![
+            unavailable,
+            TestResponse::token("token"),
+            TestResponse::json(
+                400,
+                r#"{"error": "invalid_scope", "error_description": "Invalid scope."}"#,
+            ),
+        ])
+        .await;
+        let config = AuthConfig::build(
+            &get_fake_key_json("key-1", &server.url),
+            &Usage::CloudVision,
+        )
+        .unwrap();
+
+        let token = config.generate_auth_token(3600).await.unwrap();
+        assert_eq!(token.expose_secret(), "token");
+        assert!(matches!(
+            config.generate_auth_token(3600).await,
+            Err(error) if error.kind() == ErrorKind::InvalidScope
+        ));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_unexpected_responses() {
+        let server = TestServer::start(vec![
+            TestResponse {
+                status: 502,
+                headers: vec![("content-type", String::from("text/html"))],
+                body: String::from("<html><title>502 Bad Gateway</title></html>"),
+            },
+            TestResponse {
+                status: 404,
+                headers: vec![("content-type", String::from("text/html"))],
+                body: format!("<html>{}</html>", "x".repeat(1000)),
+            },
+            TestResponse::json(400, r#"{"error": "invalid_scope"}"#),
+            TestResponse::json(200, ""),
+            TestResponse::json(
+                200,
+                r#"{"access_token": "long-lived", "expires_in": 86400, "scope": "extra"}"#,
+            ),
+        ])
+        .await;
+        let config = AuthConfig::build(
+            &get_fake_key_json("key-1", &server.url),
+            &Usage::CloudVision,
+        )
+        .unwrap()
+        .with_retry_policy(RetryPolicy::none());
+
+        let error = config.generate_auth_token(3600).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unavailable);
+        assert!(
+            matches!(&error, Error::ServiceUnavailable(502, None, body) if body.contains("502 Bad Gateway"))
+        );
+        let error = config.generate_auth_token(3600).await.unwrap_err();
+        assert_eq!(error.status(), Some(404));
+        assert!(
+            matches!(&error, Error::UnexpectedResponse(_, body) if body.starts_with("<html>") && body.len() < 600)
+        );
+        let error = config.generate_auth_token(3600).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidScope);
+        let error = config.generate_auth_token(3600).await.unwrap_err();
+        assert!(matches!(error, Error::UnexpectedResponse(200, _)));
+        let token = config.access_token().await.unwrap();
+        assert_eq!(token.as_str(), "long-lived");
+        assert!(token.expires_at() > chrono::Utc::now() + chrono::Duration::hours(23));
+    }
+
+    #[tokio::test]
+    async fn test_timeouts() {
+        // Accepts connections but never answers.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let _stream = stream;
+                    std::future::pending::<()>().await;
+                });
+            }
+        });
+        let config = AuthConfig::build(&get_fake_key_json("key-1", &url), &Usage::CloudVision)
+            .unwrap()
+            .with_retry_policy(RetryPolicy::none());
+
+        let config = config.with_request_timeout(Some(Duration::from_millis(100)));
+        assert!(matches!(
+            config.generate_auth_token(3600).await,
+            Err(Error::Timeout(_))
+        ));
+
+        let config = config.with_request_timeout(None);
+        assert!(matches!(
+            config
+                .generate_auth_token_with_deadline(3600, Duration::from_millis(100))
+                .await,
+            Err(Error::Timeout(_))
+        ));
+        let config = config.with_deadline(Some(Duration::from_millis(100)));
+        assert!(matches!(
+            config.access_token().await,
+            Err(Error::Timeout(_))
+        ));
+        assert!(matches!(
+            config
+                .generate_id_token("https://service.example.com")
+                .await,
+            Err(Error::Timeout(_))
+        ));
+        assert_eq!(config.active_key_id().as_deref(), Some("key-1"));
+    }
+
+    #[tokio::test]
+    async fn test_key_rotation() {
+        let server = TestServer::start(vec![
+            TestResponse::json(
+                400,
+                r#"{"error": "invalid_grant", "error_description": "Invalid JWT Signature."}"#,
+            ),
+            TestResponse::token("new-token"),
+            TestResponse::token("next-token"),
+        ])
+        .await;
+        let config = AuthConfig::build_with_keys(
+            &[
+                get_fake_key_json("deleted-key", &server.url),
+                get_fake_key_json("new-key", &server.url),
+            ],
+            &Usage::CloudVision,
+        )
+        .unwrap();
+        assert_eq!(config.active_key_id().as_deref(), Some("deleted-key"));
+
+        let token = config.generate_auth_token(3600).await.unwrap();
+        assert_eq!(token.expose_secret(), "new-token");
+        assert_eq!(config.active_key_id().as_deref(), Some("new-key"));
+        let token = config.generate_auth_token(3600).await.unwrap();
+        assert_eq!(token.expose_secret(), "next-token");
+
+        let kids: Vec<Option<String>> = server
+            .requests()
+            .iter()
+            .map(|request| {
+                let assertion = request.split("assertion=").nth(1).unwrap();
+                jsonwebtoken::decode_header(assertion).unwrap().kid
+            })
+            .collect();
+        assert_eq!(
+            kids,
+            [
+                Some(String::from("deleted-key")),
+                Some(String::from("new-key")),
+                Some(String::from("new-key"))
+            ]
+        );
+    }
+
+    /// A clock that runs `offset` seconds off the system clock.
+    #[derive(Clone, Default)]
+    struct TestClock(Arc<AtomicI64>);
+
+    impl Clock for TestClock {
+        fn now(&self) -> DateTime<Utc> {
+            Utc::now() + chrono::Duration::seconds(self.0.load(Ordering::Relaxed))
+        }
+    }
+
+    /// The claims of the assertion in a recorded token request.
+    #[allow(clippy::nursery)]
+    pub(crate) fn assertion_claims(request: &str) -> serde_json::Value {
+        let assertion = request.split("assertion=").nth(1).unwrap();
+        let claims = assertion.split('.').nth(1).unwrap();
+        serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(claims).unwrap()).unwrap()
+    }
+
+    fn issued_at(request: &str) -> i64 {
+        assertion_claims(request)["iat"].as_i64().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_scoped_caches() {
+        let server = TestServer::start(vec![TestResponse::token("scoped-token")]).await;
+        let config =
+            AuthConfig::build(&get_fake_key_json("key", &server.url), &Usage::CloudVision).unwrap();
+        let storage = "https://www.googleapis.com/auth/devstorage.read_only";
+        let platform = "https://www.googleapis.com/auth/cloud-platform";
+
+        let usage = Usage::Custom(format!("{storage} {platform}"));
+        let token = config.access_token_for(&usage).await.unwrap();
+        assert_eq!(token.as_str(), "scoped-token");
+        let usage = Usage::Custom(format!("{platform}  {storage} {platform}"));
+        let cached = config.access_token_for(&usage).await.unwrap();
+        assert_eq!(cached.as_str(), "scoped-token");
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            assertion_claims(&requests[0])["scope"],
+            format!("{platform} {storage}")
+        );
+
+        for index in 0..=MAX_SCOPED_CACHES {
+            config.scoped_cache(&format!("scope-{index}"));
+        }
+        assert_eq!(
+            config.scoped_caches.lock().unwrap().len(),
+            MAX_SCOPED_CACHES
+        );
+    }
+
+    #[tokio::test]
+    async fn test_id_token() {
+        let server = TestServer::start(vec![
+            TestResponse::json(
+                400,
+                r#"{"error": "invalid_grant", "error_description": "Invalid JWT Signature."}"#,
+            ),
+            TestResponse {
+                headers: vec![("date", (Utc::now() + chrono::Duration::seconds(600)).to_rfc2822())],
+                ..TestResponse::json(
+                    400,
+                    r#"{"error": "invalid_grant", "error_description": "Invalid JWT: Token must be a short-lived token (60 minutes) and in a reasonable timeframe. Check your iat and exp values in the JWT claim."}"#,
+                )
+            },
+            TestResponse::json(200, r#"{"id_token": "id-token"}"#),
+        ])
+        .await;
+        let config = AuthConfig::build_with_keys(
+            &[
+                get_fake_key_json("deleted-key", &server.url),
+                get_fake_key_json("new-key", &server.url),
+            ],
+            &Usage::CloudVision,
+        )
+        .unwrap()
+        .with_retry_policy(RetryPolicy::none());
+        let token = config
+            .generate_id_token("https://service.example.com")
+            .await
+            .unwrap();
+        assert_eq!(token.expose_secret(), "id-token");
+        assert_eq!(config.active_key_id().as_deref(), Some("new-key"));
+        assert!((config.clock_skew() - 600).abs() <= 2);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        let claims = assertion_claims(&requests[2]);
+        assert_eq!(claims["target_audience"], "https://service.example.com");
+        assert!(claims.get("scope").is_none());
+        let now = Utc::now().timestamp();
+        assert!((issued_at(&requests[1]) - (now - 10)).abs() <= 2);
+        assert!((issued_at(&requests[2]) - (now + 590)).abs() <= 2);
+    }
+
+    #[tokio::test]
+    async fn test_clock_skew() {
+        let server = TestServer::start(vec![
+            TestResponse {
+                headers: vec![("date", Utc::now().to_rfc2822())],
+                ..TestResponse::json(
+                    400,
+                    r#"{"error": "invalid_grant", "error_description": "Invalid JWT: Token must be a short-lived token (60 minutes) and in a reasonable timeframe. Check your iat and exp values in the JWT claim."}"#,
+                )
+            },
+            TestResponse::token("first-token"),
+            TestResponse::token("second-token"),
+        ])
+        .await;
+        let clock = TestClock::default();
+        clock.0.store(-600, Ordering::Relaxed);
+        let config = AuthConfig::build(&get_fake_key_json("key", &server.url), &Usage::CloudVision)
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_retry_policy(RetryPolicy::none());
+
+        let token = config.access_token().await.unwrap();
+        assert_eq!(token.as_str(), "first-token");
+        assert!((config.clock_skew() - 600).abs() <= 2);
+        let requests = server.requests();
+        let now = Utc::now().timestamp();
+        assert!((issued_at(&requests[0]) - (now - 610)).abs() <= 2);
+        assert!((issued_at(&requests[1]) - (now - 10)).abs() <= 2);
+
+        let cached = config.access_token().await.unwrap();
+        assert_eq!(cached.as_str(), "first-token");
+        clock.0.store(-600 + 3300, Ordering::Relaxed);
+        let refreshed = config.access_token().await.unwrap();
+        assert_eq!(refreshed.as_str(), "second-token");
+    }
+
+    #[test]
+    fn test_inconsistent_keys() {
+        let mut other_account: serde_json::Value =
+            serde_json::from_str(&get_fake_key_json("other", "http://localhost")).unwrap();
+        other_account["client_email"] = "other@test.iam.gserviceaccount.com".into();
+        let keys = [
+            get_fake_key_json("first", "http://localhost"),
+            other_account.to_string(),
+        ];
+        assert!(matches!(
+            AuthConfig::build_with_keys(&keys, &Usage::CloudVision),
+            Err(Error::InconsistentKeys(_))
+        ));
+        assert!(matches!(
+            AuthConfig::build_with_keys(&[] as &[&str], &Usage::CloudVision),
+            Err(Error::InconsistentKeys(_))
+        ));
+    }
+
+    #[test]
+    fn test_build_validation() {
+        let build = |field: &str, value: serde_json::Value| {
+            let mut account: serde_json::Value = serde_json::from_str(&get_fake_key_json(
+                "key-1",
+                "https://oauth2.googleapis.com/token",
+            ))
+            .unwrap();
+            if value.is_null() {
+                account.as_object_mut().unwrap().remove(field);
+            } else {
+                account[field] = value;
+            }
+            AuthConfig::build(&account.to_string(), &Usage::CloudVision).err()
+        };
+
+        assert!(build("client_x509_cert_url", serde_json::Value::Null).is_none());
+        assert!(build("project_id", serde_json::Value::Null).is_none());
+        assert!(matches!(
+            build("type", serde_json::Value::Null),
+            Some(Error::MissingField(field)) if field == "type"
+        ));
+        assert!(matches!(
+            build("type", "authorized_user".into()),
+            Some(Error::UnsupportedCredentialType(kind)) if kind == "authorized_user"
+        ));
+        assert!(matches!(
+            build("client_email", serde_json::Value::Null),
+            Some(Error::MissingField(field)) if field == "client_email"
+        ));
+        assert!(matches!(
+            build("private_key_id", 42.into()),
+            Some(Error::InvalidFieldType(field)) if field == "private_key_id"
+        ));
+        assert!(matches!(
+            build("client_email", "test-client".into()),
+            Some(Error::InvalidClientEmail(_))
+        ));
+        assert!(matches!(
+            build("token_uri", "http://oauth2.googleapis.com/token".into()),
+            Some(Error::InvalidTokenUri(_))
+        ));
+        assert!(build("token_uri", "http://127.0.0.1:8080/token".into()).is_none());
+        assert!(matches!(
+            build("private_key", "PRIVATE_KEY".into()),
+            Some(Error::InvalidPrivateKey(PrivateKeyError::NotPem))
+        ));
+    }
+
+    #[test]
+    fn test_quota_project() {
+        let mut account: serde_json::Value = serde_json::from_str(&get_fake_key_json(
+            "key",
+            "https://oauth2.googleapis.com/token",
+        ))
+        .unwrap();
+        account["quota_project_id"] = "billing-project".into();
+        let config = AuthConfig::build(&account.to_string(), &Usage::CloudVision).unwrap();
+        assert_eq!(config.quota_project_id(), Some("billing-project"));
+
+        let config = config.with_quota_project_id(Some("other-project"));
+        let provider: Arc<dyn TokenProvider> = Arc::new(config);
+        assert_eq!(provider.quota_project_id(), Some("other-project"));
+
+        account["quota_project_id"] = 42.into();
+        assert!(matches!(
+            AuthConfig::build(&account.to_string(), &Usage::CloudVision),
+            Err(Error::InvalidFieldType(field)) if field == "quota_project_id"
+        ));
+    }
+
+    #[test]
+    fn test_universe_domain() {
+        let build = |universe_domain: &str, token_uri: &str| {
+            let mut account: serde_json::Value =
+                serde_json::from_str(&get_fake_key_json("key-1", token_uri)).unwrap();
+            account["universe_domain"] = universe_domain.into();
+            AuthConfig::build(&account.to_string(), &Usage::CloudVision)
+        };
+
+        let config = build("googleapis.com", "https://oauth2.googleapis.com/token").unwrap();
+        assert_eq!(config.universe_domain(), "googleapis.com");
+        // Older key files still name the legacy token uri.
+        let legacy = get_fake_key_json("key-1", "https://accounts.google.com/o/oauth2/token");
+        assert!(AuthConfig::build(&legacy, &Usage::CloudVision).is_ok());
+        assert!(AuthConfig::build_from_p12(
+            &fs::read("tests/test-key.p12").unwrap(),
+            "test@test.iam.gserviceaccount.com",
+            "https://accounts.google.com/o/oauth2/token",
+            &Usage::CloudVision,
+        )
+        .is_ok());
+        let config = build("example-cloud.eu", "https://oauth2.example-cloud.eu/token").unwrap();
+        assert_eq!(TokenProvider::universe_domain(&config), "example-cloud.eu");
+        assert!(matches!(
+            build("example-cloud.eu", "https://oauth2.googleapis.com/token"),
+            Err(Error::UniverseDomainMismatch(_, _))
+        ));
+        let iam_config = AuthConfig::build_with_iam_signer(
+            IamSigner::build(
+                build("example-cloud.eu", "https://oauth2.example-cloud.eu/token").unwrap(),
+                "target@test.iam.gserviceaccount.com",
+            ),
+            &Usage::CloudVision,
+        );
+        assert_eq!(iam_config.aud, "https://oauth2.example-cloud.eu/token");
+        assert!(matches!(
+            config.with_universe_domain("googleapis.com"),
+            Err(Error::UniverseDomainMismatch(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_debug_redacts_private_key() {
+        let json = get_fake_key_json("key-1", "https://oauth2.googleapis.com/token");
+        let account: ServiceAccountInfoJson = serde_json::from_str(&json).unwrap();
+        let private_key = account.private_key.expose_secret().to_string();
+        assert!(!format!("{account:?}").contains(&private_key));
+
+        let config = AuthConfig::build(&json, &Usage::CloudVision).unwrap();
+        let debug = format!("{config:?}");
+        assert!(debug.contains("scope"));
+        assert!(!debug.contains("key-1"));
+        assert!(!debug.contains(&private_key));
+    }
+
+    /// The fake key file with the provided key id and token uri.
+    #[allow(clippy::nursery)]
+    pub(crate) fn get_fake_key_json(private_key_id: &str, token_uri: &str) -> String {
+        let mut account: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string("tests/invalid-value-client.json").unwrap())
+                .unwrap();
+        account["private_key_id"] = private_key_id.into();
+        account["token_uri"] = token_uri.into();
+        account.to_string()
+    }
+
     fn get_valid_config_complete() -> AuthConfig {
         get_valid_config(&Usage::CloudVision)
     }
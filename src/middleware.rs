@@ -0,0 +1,161 @@
+use crate::error::Result;
+use crate::headers::AuthHeaders;
+use crate::provider::{AccessToken, TokenProvider};
+use crate::usage::Usage;
+use crate::AuthConfig;
+use async_trait::async_trait;
+use http::Extensions;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{Middleware, Next};
+use std::sync::Arc;
+
+/// A request extension that replaces the scope of the token for a single request.
+///
+/// It is attached with `reqwest_middleware::RequestBuilder::with_extension`,
+/// the tokens of each scope are buffered separately, see [`AuthConfig::access_token_for`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScopeOverride(pub Usage);
+
+/// This struct is a [`Middleware`] for a `reqwest_middleware::ClientWithMiddleware`
+/// that authorizes every request with a token of an [`AuthConfig`].
+///
+/// The token is taken from the buffer of the configuration and sent as `Authorization: Bearer` header.
+/// If the api answers `401 Unauthorized`, the token is refreshed and the request is sent once more.
+/// Requests with a streaming body cannot be replayed and return the `401` response.
+pub struct AuthMiddleware {
+    config: Arc<AuthConfig>,
+}
+
+impl AuthMiddleware {
+    /// This function generates a middleware that authorizes the requests with the tokens of the `config`.
+    #[must_use]
+    pub fn new(config: AuthConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+        }
+    }
+
+    /// This function generates a middleware that shares the `config` and its token buffer with other users.
+    #[must_use]
+    pub const fn from_arc(config: Arc<AuthConfig>) -> Self {
+        Self { config }
+    }
+
+    async fn token(&self, scope: Option<&Usage>, refresh: bool) -> Result<AccessToken> {
+        match (scope, refresh) {
+            (Some(usage), false) => self.config.access_token_for(usage).await,
+            (Some(usage), true) => self.config.refresh_access_token_for(usage).await,
+            (None, false) => self.config.access_token().await,
+            (None, true) => self.config.refresh_access_token().await,
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for AuthMiddleware {
+    async fn handle(
+        &self,
+        mut request: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let scope = extensions
+            .get::<ScopeOverride>()
+            .map(|scope| scope.0.clone());
+        let token = self
+            .token(scope.as_ref(), false)
+            .await
+            .map_err(reqwest_middleware::Error::middleware)?;
+        set_authorization(&mut request, &token, self.config.quota_project_id())?;
+        let retry = request.try_clone();
+        let response = next.clone().run(request, extensions).await?;
+        let Some(mut retry) = retry.filter(|_| response.status() == StatusCode::UNAUTHORIZED)
+        else {
+            return Ok(response);
+        };
+        let token = self
+            .token(scope.as_ref(), true)
+            .await
+            .map_err(reqwest_middleware::Error::middleware)?;
+        set_authorization(&mut retry, &token, self.config.quota_project_id())?;
+        next.run(retry, extensions).await
+    }
+}
+
+/// Sets the headers of the token and the quota project, see [`AuthHeaders`].
+fn set_authorization(
+    request: &mut Request,
+    token: &AccessToken,
+    quota_project_id: Option<&str>,
+) -> reqwest_middleware::Result<()> {
+    AuthHeaders::build(token, quota_project_id)
+        .map_err(reqwest_middleware::Error::middleware)?
+        .apply(request.headers_mut());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::test_server::{TestResponse, TestServer};
+    use crate::tests::{assertion_claims, get_fake_key_json};
+
+    #[tokio::test]
+    async fn test_retry_on_unauthorized() {
+        let token_server = TestServer::start(vec![
+            TestResponse::token("stale-token"),
+            TestResponse::token("fresh-token"),
+            TestResponse::token("storage-token"),
+        ])
+        .await;
+        let api_server = TestServer::start(vec![
+            TestResponse::json(401, "{}"),
+            TestResponse::json(200, "{}"),
+            TestResponse::json(200, "{}"),
+        ])
+        .await;
+        let config = AuthConfig::build(
+            &get_fake_key_json("key", &token_server.url),
+            &Usage::CloudVision,
+        )
+        .unwrap();
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(AuthMiddleware::new(config))
+            .build();
+
+        let response = client.get(&api_server.url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = client
+            .get(&api_server.url)
+            .with_extension(ScopeOverride(Usage::DevStorageReadOnly))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let authorizations: Vec<String> = api_server
+            .requests()
+            .iter()
+            .map(|request| {
+                request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("authorization: "))
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(
+            authorizations,
+            [
+                "Bearer stale-token",
+                "Bearer fresh-token",
+                "Bearer storage-token"
+            ]
+        );
+        assert_eq!(
+            assertion_claims(&token_server.requests()[2])["scope"],
+            Usage::DevStorageReadOnly.as_string()
+        );
+    }
+}
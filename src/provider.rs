@@ -0,0 +1,137 @@
+use crate::error::Result;
+use crate::secret::SecretString;
+use crate::universe::DEFAULT_UNIVERSE_DOMAIN;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+/// The environment variable that overrides the quota project of credential files.
+#[cfg(feature = "http")]
+static QUOTA_PROJECT_ENV: &str = "GOOGLE_CLOUD_QUOTA_PROJECT";
+
+/// An authentication token (short: `auth_token`) together with the point in time it expires.
+/// The `Debug` output redacts the token, see [`SecretString`].
+#[derive(Clone, Debug)]
+pub struct AccessToken {
+    token: SecretString,
+    expires_at: DateTime<Utc>,
+}
+
+impl AccessToken {
+    /// Creates a new token that expires at `expires_at`.
+    #[must_use]
+    pub fn new(token: impl Into<SecretString>, expires_at: DateTime<Utc>) -> Self {
+        Self {
+            token: token.into(),
+            expires_at,
+        }
+    }
+
+    /// Creates a new token that expires `expires_in` seconds after `now`.
+    #[must_use]
+    pub fn expiring_in(
+        token: impl Into<SecretString>,
+        expires_in: i64,
+        now: DateTime<Utc>,
+    ) -> Self {
+        Self::new(token, now + chrono::Duration::seconds(expires_in))
+    }
+
+    /// The `auth_token` that is sent as bearer token to the google api services.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        self.token.expose_secret()
+    }
+
+    /// The point in time after which the `auth_token` is no longer accepted.
+    #[must_use]
+    pub const fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
+
+    /// Consumes the token and returns the `auth_token`.
+    #[must_use]
+    pub fn into_secret(self) -> SecretString {
+        self.token
+    }
+}
+
+/// A source of authentication tokens.
+///
+/// Every credential type of this crate implements this trait, so they can be used interchangeably
+/// as the base credential of other credentials, e.g. for [`crate::impersonated::ImpersonatedCredentials`].
+#[async_trait]
+pub trait TokenProvider: Send + Sync {
+    /// Requests a new authentication token with the scope and the default lifetime of the credential.
+    /// # Errors
+    /// See [`crate::Error`] for a more detailed answer.
+    async fn access_token(&self) -> Result<AccessToken>;
+
+    /// Discards a buffered token and requests a new one, e.g. after an api service rejected it with `401`.
+    /// Credentials without a token buffer request a new token anyway.
+    /// # Errors
+    /// See [`crate::Error`] for a more detailed answer.
+    async fn refresh_access_token(&self) -> Result<AccessToken> {
+        self.access_token().await
+    }
+
+    /// The universe domain the tokens are valid in, `googleapis.com` for the public Google cloud.
+    /// Credentials built on top of this provider derive their default endpoints from it.
+    fn universe_domain(&self) -> &str {
+        DEFAULT_UNIVERSE_DOMAIN
+    }
+
+    /// The project that is billed for the api requests, sent as `x-goog-user-project` header,
+    /// see [`crate::headers::AuthHeaders`]. Without one, the project of the credential is billed.
+    fn quota_project_id(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// The quota project of the `GOOGLE_CLOUD_QUOTA_PROJECT` environment variable, otherwise the one of the credential file.
+#[cfg(feature = "http")]
+#[allow(clippy::nursery)]
+pub(crate) fn resolve_quota_project_id(file_value: Option<String>) -> Option<String> {
+    std::env::var(QUOTA_PROJECT_ENV)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .or(file_value)
+}
+
+#[async_trait]
+impl<T: TokenProvider + ?Sized> TokenProvider for Arc<T> {
+    async fn access_token(&self) -> Result<AccessToken> {
+        (**self).access_token().await
+    }
+
+    async fn refresh_access_token(&self) -> Result<AccessToken> {
+        (**self).refresh_access_token().await
+    }
+
+    fn universe_domain(&self) -> &str {
+        (**self).universe_domain()
+    }
+
+    fn quota_project_id(&self) -> Option<&str> {
+        (**self).quota_project_id()
+    }
+}
+
+#[async_trait]
+impl<T: TokenProvider + ?Sized> TokenProvider for Box<T> {
+    async fn access_token(&self) -> Result<AccessToken> {
+        (**self).access_token().await
+    }
+
+    async fn refresh_access_token(&self) -> Result<AccessToken> {
+        (**self).refresh_access_token().await
+    }
+
+    fn universe_domain(&self) -> &str {
+        (**self).universe_domain()
+    }
+
+    fn quota_project_id(&self) -> Option<&str> {
+        (**self).quota_project_id()
+    }
+}
@@ -0,0 +1,269 @@
+use crate::error::{Result, TokenGenerationError};
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+/// This struct describes how often and how long a failed token request is retried.
+///
+/// Only transient failures are retried, see [`crate::error::ErrorKind::is_retryable`]: connection errors and request timeouts,
+/// `429` and `5xx` responses and temporary error codes like `temporarily_unavailable` of the authentication service.
+/// Rejected credentials like `invalid_grant` or `invalid_scope` fail right away.
+/// The delay doubles with every attempt, starting at the base delay and capped by the maximum delay.
+/// A `Retry-After` header of the response replaces the computed delay.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, starting with a delay of 200ms that is capped at 5 seconds,
+    /// with jitter and a total deadline of 30 seconds.
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+            jitter: true,
+            deadline: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that sends every request only once.
+    #[must_use]
+    pub fn none() -> Self {
+        Self::default().with_max_attempts(1)
+    }
+
+    /// Sets the number of attempts including the first one. Values below 1 are treated as 1.
+    #[must_use]
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the delay before the first retry.
+    #[must_use]
+    pub const fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Sets the upper bound of the delay between two attempts.
+    #[must_use]
+    pub const fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Enables or disables the full jitter, which picks a random delay up to the computed one,
+    /// so many clients do not retry at the same moment.
+    #[must_use]
+    pub const fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the total time after which no further attempt is started.
+    /// `None` only limits the number of attempts.
+    #[must_use]
+    pub const fn with_deadline(mut self, deadline: Option<Duration>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Runs the operation until it succeeds, fails permanently or the policy is exhausted.
+    /// The error of the last attempt is returned.
+    pub(crate) async fn run<T, F, Fut>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = Result<T>> + Send,
+    {
+        let start = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let error = match operation().await {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            let Some(delay) = self.next_delay(attempt, start, &error) else {
+                return Err(error);
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Works like [`RetryPolicy::run`], but blocks the current thread between the attempts.
+    #[cfg(feature = "blocking")]
+    pub(crate) fn run_blocking<T, F>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Result<T>,
+    {
+        let start = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let error = match operation() {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            let Some(delay) = self.next_delay(attempt, start, &error) else {
+                return Err(error);
+            };
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    /// The delay before the next attempt, `None` if the `error` of the failed `attempt` is final.
+    fn next_delay(
+        &self,
+        attempt: u32,
+        start: Instant,
+        error: &TokenGenerationError,
+    ) -> Option<Duration> {
+        if attempt >= self.max_attempts || !error.is_retryable() {
+            return None;
+        }
+        let delay = retry_after(error).unwrap_or_else(|| self.backoff(attempt));
+        if self
+            .deadline
+            .is_some_and(|deadline| start.elapsed() + delay > deadline)
+        {
+            return None;
+        }
+        Some(delay)
+    }
+
+    /// The delay after the failed `attempt`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2_u32.saturating_pow(attempt - 1))
+            .min(self.max_delay);
+        if !self.jitter {
+            return delay;
+        }
+        let nanos = u64::try_from(delay.as_nanos()).unwrap_or(u64::MAX);
+        Duration::from_nanos(random() % nanos.saturating_add(1))
+    }
+}
+
+const fn retry_after(error: &TokenGenerationError) -> Option<Duration> {
+    match error {
+        TokenGenerationError::ServiceUnavailable(_, retry_after, _) => *retry_after,
+        _ => None,
+    }
+}
+
+/// Parses a `Retry-After` header, either in seconds or as HTTP date.
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    Some(
+        (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// A random number for the jitter, taken from the randomly seeded hasher of the standard library.
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_run() {
+        let policy = RetryPolicy::default()
+            .with_base_delay(Duration::from_millis(1))
+            .with_jitter(false);
+        let attempts = AtomicU32::new(0);
+        let result = policy
+            .run(|| async {
+                match attempts.fetch_add(1, Ordering::Relaxed) {
+                    0 => Err(TokenGenerationError::ServiceUnavailable(
+                        503,
+                        None,
+                        String::new(),
+                    )),
+                    1 => Err(TokenGenerationError::AuthenticationError {
+                        error: String::from("temporarily_unavailable"),
+                        description: String::new(),
+                        status: Some(503),
+                    }),
+                    _ => Ok("token"),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), "token");
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+
+        for code in ["invalid_grant", "invalid_scope"] {
+            let attempts = AtomicU32::new(0);
+            let result: Result<()> = policy
+                .run(|| async {
+                    attempts.fetch_add(1, Ordering::Relaxed);
+                    Err(TokenGenerationError::AuthenticationError {
+                        error: code.to_string(),
+                        description: String::new(),
+                        status: Some(400),
+                    })
+                })
+                .await;
+            assert!(result.is_err());
+            assert_eq!(attempts.load(Ordering::Relaxed), 1);
+        }
+
+        let attempts = AtomicU32::new(0);
+        let result: Result<()> = policy
+            .clone()
+            .with_deadline(Some(Duration::from_secs(1)))
+            .run(|| async {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                Err(TokenGenerationError::ServiceUnavailable(
+                    429,
+                    Some(Duration::from_mins(1)),
+                    String::new(),
+                ))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default()
+            .with_base_delay(Duration::from_millis(100))
+            .with_max_delay(Duration::from_millis(300))
+            .with_jitter(false);
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(300));
+        assert!(policy.with_jitter(true).backoff(3) <= Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_mins(2)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert!(parse_retry_after("soon").is_none());
+    }
+}
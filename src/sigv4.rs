@@ -0,0 +1,245 @@
+use crate::error::{Result, TokenGenerationError};
+use crate::secret::SecretString;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use zeroize::Zeroizing;
+
+static ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// Characters that must be encoded in canonical AWS query strings.
+/// Only the unreserved characters `A-Z a-z 0-9 - _ . ~` stay untouched.
+#[allow(clippy::nursery)]
+pub(crate) const AWS_URI_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Temporary or long-term AWS security credentials used to sign requests.
+/// The secret access key is zeroized on drop.
+#[allow(clippy::nursery)]
+pub(crate) struct AwsSecurityCredentials {
+    pub(crate) access_key_id: String,
+    pub(crate) secret_access_key: SecretString,
+    pub(crate) session_token: Option<String>,
+}
+
+/// Signs AWS api requests with the
+/// [Signature Version 4](https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_aws-signing.html) procedure.
+#[allow(clippy::nursery)]
+pub(crate) struct AwsRequestSigner<'a> {
+    pub(crate) credentials: &'a AwsSecurityCredentials,
+    pub(crate) region: &'a str,
+    pub(crate) service: &'a str,
+}
+
+impl AwsRequestSigner<'_> {
+    /// Signs the described request and returns all headers that have to be sent with it.
+    /// The returned list contains the provided `headers`, the `host`, `x-amz-date`,
+    /// optionally `x-amz-security-token` and finally the `authorization` header.
+    pub(crate) fn sign(
+        &self,
+        method: &str,
+        url: &str,
+        headers: &[(&str, &str)],
+        payload: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<(String, String)>> {
+        let url = Url::parse(url).map_err(|e| {
+            TokenGenerationError::AwsConfigurationError(format!("Invalid request url '{url}': {e}"))
+        })?;
+        let host = url.host_str().ok_or_else(|| {
+            TokenGenerationError::AwsConfigurationError(format!("Request url '{url}' has no host."))
+        })?;
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        let mut signed_headers: Vec<(String, String)> = headers
+            .iter()
+            .map(|(key, value)| (key.to_lowercase(), value.trim().to_string()))
+            .collect();
+        signed_headers.push((String::from("host"), host.to_string()));
+        signed_headers.push((String::from("x-amz-date"), amz_date.clone()));
+        if let Some(token) = &self.credentials.session_token {
+            signed_headers.push((String::from("x-amz-security-token"), token.clone()));
+        }
+        signed_headers.sort();
+
+        let canonical_headers =
+            signed_headers
+                .iter()
+                .fold(String::new(), |mut canonical, (key, value)| {
+                    let _ = writeln!(canonical, "{key}:{value}");
+                    canonical
+                });
+        let signed_header_names = signed_headers
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{method}\n{}\n{}\n{canonical_headers}\n{signed_header_names}\n{}",
+            canonical_uri(&url),
+            canonical_query(&url),
+            hex::encode(Sha256::digest(payload.as_bytes())),
+        );
+
+        let scope = format!("{date}/{}/{}/aws4_request", self.region, self.service);
+        let string_to_sign = format!(
+            "{ALGORITHM}\n{amz_date}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let signing_key = [
+            self.region.as_bytes(),
+            self.service.as_bytes(),
+            b"aws4_request",
+        ]
+        .iter()
+        .fold(
+            hmac_sha256(
+                Zeroizing::new(format!(
+                    "AWS4{}",
+                    self.credentials.secret_access_key.expose_secret()
+                ))
+                .as_bytes(),
+                date.as_bytes(),
+            ),
+            |key, data| hmac_sha256(&key, data),
+        );
+        let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+        signed_headers.push((
+            String::from("authorization"),
+            format!(
+                "{ALGORITHM} Credential={}/{scope}, SignedHeaders={signed_header_names}, Signature={signature}",
+                self.credentials.access_key_id
+            ),
+        ));
+        Ok(signed_headers)
+    }
+}
+
+fn canonical_uri(url: &Url) -> String {
+    let path = url.path();
+    if path.is_empty() {
+        String::from("/")
+    } else {
+        path.to_string()
+    }
+}
+
+fn canonical_query(url: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| {
+            (
+                utf8_percent_encode(&key, AWS_URI_ENCODE).to_string(),
+                utf8_percent_encode(&value, AWS_URI_ENCODE).to_string(),
+            )
+        })
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Example taken from the AWS documentation of the signing procedure.
+    #[test]
+    fn test_sign_documentation_example() {
+        let credentials = AwsSecurityCredentials {
+            access_key_id: String::from("AKIDEXAMPLE"),
+            secret_access_key: SecretString::from(String::from(
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            )),
+            session_token: None,
+        };
+        let signer = AwsRequestSigner {
+            credentials: &credentials,
+            region: "us-east-1",
+            service: "iam",
+        };
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let headers = signer
+            .sign(
+                "GET",
+                "https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08",
+                &[(
+                    "Content-Type",
+                    "application/x-www-form-urlencoded; charset=utf-8",
+                )],
+                "",
+                now,
+            )
+            .unwrap();
+
+        let authorization = headers
+            .iter()
+            .find(|(key, _)| key == "authorization")
+            .map(|(_, value)| value.as_str())
+            .unwrap();
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn test_sign_with_session_token() {
+        let credentials = AwsSecurityCredentials {
+            access_key_id: String::from("AKIDEXAMPLE"),
+            secret_access_key: SecretString::from(String::from("secret")),
+            session_token: Some(String::from("session")),
+        };
+        let signer = AwsRequestSigner {
+            credentials: &credentials,
+            region: "eu-west-1",
+            service: "sts",
+        };
+        let headers = signer
+            .sign(
+                "POST",
+                "https://sts.eu-west-1.amazonaws.com?Action=GetCallerIdentity&Version=2011-06-15",
+                &[],
+                "",
+                Utc::now(),
+            )
+            .unwrap();
+        let names: Vec<&str> = headers.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "host",
+                "x-amz-date",
+                "x-amz-security-token",
+                "authorization"
+            ]
+        );
+        assert!(headers[3]
+            .1
+            .contains("SignedHeaders=host;x-amz-date;x-amz-security-token"));
+    }
+}
@@ -0,0 +1,99 @@
+#![allow(clippy::unwrap_used, clippy::nursery)]
+
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// A canned HTTP response of the [`TestServer`].
+pub(crate) struct TestResponse {
+    pub(crate) status: u16,
+    pub(crate) headers: Vec<(&'static str, String)>,
+    pub(crate) body: String,
+}
+
+impl TestResponse {
+    pub(crate) fn json(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            headers: vec![("content-type", String::from("application/json"))],
+            body: body.to_string(),
+        }
+    }
+
+    pub(crate) fn token(access_token: &str) -> Self {
+        Self::json(
+            200,
+            &format!(
+                r#"{{"access_token": "{access_token}", "expires_in": 3599, "token_type": "Bearer"}}"#
+            ),
+        )
+    }
+}
+
+/// A local HTTP server that answers the requests in order with the provided responses
+/// and records the raw requests.
+pub(crate) struct TestServer {
+    pub(crate) url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestServer {
+    pub(crate) async fn start(responses: Vec<TestResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let request = read_request(&mut stream).await;
+                recorded.lock().unwrap().push(request);
+                let mut raw = format!(
+                    "HTTP/1.1 {} Test\r\ncontent-length: {}\r\nconnection: close\r\n",
+                    response.status,
+                    response.body.len()
+                );
+                for (key, value) in &response.headers {
+                    let _ = write!(raw, "{key}: {value}\r\n");
+                }
+                raw.push_str("\r\n");
+                raw.push_str(&response.body);
+                stream.write_all(raw.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+        Self { url, requests }
+    }
+
+    /// The raw requests (head and body) received so far.
+    pub(crate) fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn read_request(stream: &mut tokio::net::TcpStream) -> String {
+    let mut raw = Vec::new();
+    let mut buffer = [0_u8; 4096];
+    loop {
+        let read = stream.read(&mut buffer).await.unwrap();
+        raw.extend_from_slice(&buffer[..read]);
+        let text = String::from_utf8_lossy(&raw).to_string();
+        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+            let length = head
+                .lines()
+                .find_map(|line| {
+                    let (key, value) = line.split_once(':')?;
+                    key.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().unwrap())
+                })
+                .unwrap_or(0);
+            if body.len() >= length {
+                return text;
+            }
+        }
+        if read == 0 {
+            return String::from_utf8_lossy(&raw).to_string();
+        }
+    }
+}
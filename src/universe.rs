@@ -0,0 +1,76 @@
+use crate::error::{Result, TokenGenerationError};
+use url::Url;
+
+/// The universe domain of the public Google cloud.
+#[allow(clippy::nursery)]
+pub(crate) static DEFAULT_UNIVERSE_DOMAIN: &str = "googleapis.com";
+/// Hosts of the public Google cloud outside of `googleapis.com`,
+/// e.g. the legacy token uri `https://accounts.google.com/o/oauth2/token` of older key files.
+static LEGACY_GOOGLE_HOSTS: [&str; 1] = ["accounts.google.com"];
+
+/// The default url of a Google service in a universe, e.g. `https://oauth2.googleapis.com`.
+#[cfg(feature = "http")]
+#[allow(clippy::nursery)]
+pub(crate) fn service_endpoint(service: &str, universe_domain: &str) -> String {
+    format!("https://{service}.{universe_domain}")
+}
+
+/// Rejects an endpoint that belongs to another universe than the credential.
+/// Loopback hosts are accepted for local emulators and fake universes in tests,
+/// the legacy Google hosts for the `googleapis.com` universe.
+#[allow(clippy::nursery)]
+pub(crate) fn check_endpoint(url: &str, universe_domain: &str) -> Result<()> {
+    let matches = Url::parse(url).is_ok_and(|url| {
+        is_loopback(&url)
+            || url.host_str().is_some_and(|host| {
+                host == universe_domain
+                    || host.ends_with(&format!(".{universe_domain}"))
+                    || (universe_domain == DEFAULT_UNIVERSE_DOMAIN
+                        && LEGACY_GOOGLE_HOSTS.contains(&host))
+            })
+    });
+    if matches {
+        Ok(())
+    } else {
+        Err(TokenGenerationError::UniverseDomainMismatch(
+            url.to_string(),
+            universe_domain.to_string(),
+        ))
+    }
+}
+
+#[allow(clippy::nursery)]
+pub(crate) fn is_loopback(url: &Url) -> bool {
+    matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_endpoint() {
+        assert!(check_endpoint("https://oauth2.googleapis.com/token", "googleapis.com").is_ok());
+        assert!(
+            check_endpoint("https://oauth2.example-cloud.eu/token", "example-cloud.eu").is_ok()
+        );
+        assert!(check_endpoint("http://127.0.0.1:8080/token", "example-cloud.eu").is_ok());
+        assert!(matches!(
+            check_endpoint("https://oauth2.googleapis.com/token", "example-cloud.eu"),
+            Err(TokenGenerationError::UniverseDomainMismatch(_, _))
+        ));
+        assert!(
+            check_endpoint("https://oauth2.notgoogleapis.com/token", "googleapis.com").is_err()
+        );
+        assert!(check_endpoint(
+            "https://accounts.google.com/o/oauth2/token",
+            "googleapis.com"
+        )
+        .is_ok());
+        assert!(check_endpoint(
+            "https://accounts.google.com/o/oauth2/token",
+            "example-cloud.eu"
+        )
+        .is_err());
+    }
+}
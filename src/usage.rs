@@ -4,6 +4,7 @@ use std::fmt::{Display, Formatter};
 
 /// This enum contains all usage types defined by google (late 2023).
 /// See [here](https://developers.google.com/identity/protocols/oauth2/scopes?hl=en) for more information.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Usage {
     /// For own purposes
     Custom(String),